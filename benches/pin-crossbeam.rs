@@ -21,7 +21,7 @@ fn crossbeam_epoch() {
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("crossbeam-epoch 2^24", |b| b.iter(|| crossbeam_epoch()));
+    c.bench_function("crossbeam-epoch 2^24", |b| b.iter(crossbeam_epoch));
 }
 
 criterion_group!(benches, criterion_benchmark);
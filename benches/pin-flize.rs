@@ -26,7 +26,7 @@ fn flize() {
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("flize 2^24", |b| b.iter(|| flize()));
+    c.bench_function("flize 2^24", |b| b.iter(flize));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -28,6 +28,23 @@ unsafe impl VirtualAllocRef for GlobalAllocator {
     }
 }
 
+/// Reacts to a failed allocation of `layout`, signalled by an allocator returning a null pointer.
+///
+/// With the `std` feature this defers to `std::alloc::handle_alloc_error` which aborts the process,
+/// otherwise it panics.
+#[cold]
+pub(crate) fn handle_alloc_error(layout: &Layout) -> ! {
+    #[cfg(feature = "std")]
+    {
+        let std_layout =
+            unsafe { stdalloc::Layout::from_size_align_unchecked(layout.size(), layout.align()) };
+        stdalloc::handle_alloc_error(std_layout)
+    }
+
+    #[cfg(not(feature = "std"))]
+    panic!("memory allocation of {} bytes failed", layout.size())
+}
+
 #[doc(hidden)]
 pub struct AllocatorMeta {
     alloc: fn(*const MaybeUninit<[u8; INLINE_DYN_SPACE]>, &Layout) -> *mut u8,
@@ -90,6 +107,12 @@ union Transmuter<F: Copy, T: Copy> {
     to: T,
 }
 
+/// A type erasable allocator that can back an `AllocRef`.
+///
+/// # Safety
+///
+/// Implementations must return memory that is valid for the requested layout
+/// and must remain usable from any thread.
 pub unsafe trait VirtualAllocRef: Send + Sync + 'static {
     /// # Safety
     ///
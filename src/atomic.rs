@@ -1,4 +1,4 @@
-use crate::{Shared, Shield, Tag};
+use crate::{Collector, Shared, Shield, Tag};
 use core::{
     fmt,
     marker::PhantomData,
@@ -16,13 +16,26 @@ where
 ///
 /// This struct provides methods for manipulating the atomic pointer via
 /// standard atomic operations using `Shared` as the corresponding non atomic version.
-#[repr(transparent)]
+///
+/// With the `branding` feature enabled debug builds remember the collector an `Atomic`
+/// is first accessed with and panic if it is later accessed with a shield of another collector.
+///
+/// # Layout
+///
+/// An `Atomic` is a single `usize` with `#[repr(transparent)]` except in debug builds with
+/// the `branding` feature enabled, where it stores the brand alongside the pointer.
+/// Cargo features are unified so enabling `branding` anywhere in the dependency graph
+/// changes the layout for every crate in debug builds. Code relying on the layout, such as
+/// transmuting between `Atomic` and `usize`, must not be used with the feature enabled.
+#[cfg_attr(not(all(feature = "branding", debug_assertions)), repr(transparent))]
 pub struct Atomic<V, T1, T2, const N1: usize, const N2: usize>
 where
     T1: Tag<N1>,
     T2: Tag<N2>,
 {
     pub(crate) data: AtomicUsize,
+    #[cfg(all(feature = "branding", debug_assertions))]
+    brand: AtomicUsize,
     _m0: PhantomData<V>,
     _m1: PhantomData<T1>,
     _m2: PhantomData<T2>,
@@ -41,6 +54,8 @@ where
     pub unsafe fn from_raw(raw: usize) -> Self {
         Self {
             data: AtomicUsize::new(raw),
+            #[cfg(all(feature = "branding", debug_assertions))]
+            brand: AtomicUsize::new(0),
             _m0: PhantomData,
             _m1: PhantomData,
             _m2: PhantomData,
@@ -63,12 +78,63 @@ where
     #[cfg(feature = "std")]
     /// This constructs a `Vec<Atomic>` with null values in an optimized manner.
     pub fn null_vec(len: usize) -> Vec<Self> {
+        // the brand makes the layout differ from `usize`
+        #[cfg(all(feature = "branding", debug_assertions))]
+        {
+            (0..len).map(|_| Self::null()).collect()
+        }
+
+        #[cfg(not(all(feature = "branding", debug_assertions)))]
         #[allow(clippy::unsound_collection_transmute)]
         unsafe {
             std::mem::transmute(vec![0_usize; len])
         }
     }
 
+    /// Brands the `Atomic` with a collector so that accessing it with shields of another collector
+    /// panics. An `Atomic` that isn't registered is instead branded with the collector of
+    /// the first shield it's accessed with.
+    ///
+    /// Branding only happens in debug builds with the `branding` feature enabled,
+    /// in all other builds this does nothing.
+    ///
+    /// # Panics
+    /// Panics if the `Atomic` has already been branded with another collector.
+    pub fn register(&self, collector: &Collector) {
+        #[cfg(all(feature = "branding", debug_assertions))]
+        self.brand_with(collector.id());
+
+        #[cfg(not(all(feature = "branding", debug_assertions)))]
+        let _ = collector;
+    }
+
+    #[cfg(all(feature = "branding", debug_assertions))]
+    fn brand_with(&self, id: usize) {
+        // unprotected shields aren't tied to a collector and are never checked
+        if id == 0 {
+            return;
+        }
+
+        if let Err(brand) = self
+            .brand
+            .compare_exchange(0, id, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            assert!(
+                brand == id,
+                "Atomic accessed with a shield of another collector than the one it belongs to"
+            );
+        }
+    }
+
+    #[inline]
+    fn check_brand<'collector, S>(&self, _shield: &S)
+    where
+        S: Shield<'collector>,
+    {
+        #[cfg(all(feature = "branding", debug_assertions))]
+        self.brand_with(_shield.collector_id());
+    }
+
     /// Load a the tagged pointer.
     pub fn load<'collector, 'shield, S>(
         &self,
@@ -78,6 +144,7 @@ where
     where
         S: Shield<'collector>,
     {
+        self.check_brand(_shield);
         let raw = self.data.load(ordering);
         unsafe { Shared::from_raw(raw) }
     }
@@ -98,6 +165,7 @@ where
     where
         S: Shield<'collector>,
     {
+        self.check_brand(_shield);
         let new_raw = new.into_raw();
         let old_raw = self.data.swap(new_raw, ordering);
         unsafe { Shared::from_raw(old_raw) }
@@ -117,6 +185,7 @@ where
     where
         S: Shield<'collector>,
     {
+        self.check_brand(_shield);
         let current_raw = current.into_raw();
         let new_raw = new.into_raw();
         let result = self
@@ -143,6 +212,7 @@ where
     where
         S: Shield<'collector>,
     {
+        self.check_brand(_shield);
         let current_raw = current.into_raw();
         let new_raw = new.into_raw();
         let result = self
@@ -192,3 +262,34 @@ where
             .finish()
     }
 }
+
+#[cfg(all(test, feature = "branding", debug_assertions))]
+mod tests {
+    use crate::{unprotected, Collector, NullTag};
+    use std::sync::atomic::Ordering;
+
+    type Atomic = crate::Atomic<u64, NullTag, NullTag, 0, 0>;
+
+    #[test]
+    fn same_collector() {
+        let collector = Collector::new();
+        let atomic = Atomic::null();
+        atomic.register(&collector);
+
+        let handle = collector.clone();
+        atomic.load(Ordering::Relaxed, &handle.full_shield());
+        atomic.load(Ordering::Relaxed, &collector.thin_shield());
+        atomic.load(Ordering::Relaxed, unsafe { unprotected() });
+    }
+
+    #[test]
+    #[should_panic(expected = "another collector")]
+    fn cross_collector() {
+        let a = Collector::new();
+        let b = Collector::new();
+        let atomic = Atomic::null();
+
+        atomic.load(Ordering::Relaxed, &a.thin_shield());
+        atomic.load(Ordering::Relaxed, &b.thin_shield());
+    }
+}
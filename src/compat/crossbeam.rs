@@ -0,0 +1,732 @@
+//! A subset of the `crossbeam-epoch` API implemented on top of flize.
+//!
+//! This module mirrors the names and signatures of `crossbeam-epoch` so that code can be migrated
+//! module by module by swapping imports. Guards pin the [`default_collector`] and pointers are
+//! stored in a flize `Atomic` with a single tag in the low bits freed up by the alignment of `T`,
+//! just like in `crossbeam-epoch`.
+//!
+//! ```
+//! use flize::compat::crossbeam::{self as epoch, Atomic, Owned};
+//! use std::sync::atomic::Ordering;
+//!
+//! let atomic = Atomic::new(1);
+//! let guard = &epoch::pin();
+//! let old = atomic.swap(Owned::new(2), Ordering::AcqRel, guard);
+//!
+//! unsafe {
+//!     assert_eq!(*old.deref(), 1);
+//!     guard.defer_destroy(old);
+//!     drop(atomic.into_owned());
+//! }
+//! ```
+//!
+//! [`default_collector`]: ../../fn.default_collector.html
+
+use crate::{NullTag, Shield, ThinShield};
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    sync::atomic::Ordering,
+};
+
+type Inner<T> = crate::Atomic<T, NullTag, NullTag, 0, 0>;
+
+/// Returns a bitmask containing the unused least significant bits of an aligned pointer to `T`.
+fn low_bits<T>() -> usize {
+    mem::align_of::<T>() - 1
+}
+
+fn compose_tag<T>(data: usize, tag: usize) -> usize {
+    (data & !low_bits::<T>()) | (tag & low_bits::<T>())
+}
+
+fn decompose_tag<T>(data: usize) -> (*mut T, usize) {
+    ((data & !low_bits::<T>()) as *mut T, data & low_bits::<T>())
+}
+
+/// Pins the current thread on the default collector.
+pub fn pin() -> Guard {
+    Guard {
+        shield: Some(crate::pin()),
+    }
+}
+
+struct UnprotectedGuard(Guard);
+
+// The unprotected guard holds no shield and is never mutated.
+unsafe impl Sync for UnprotectedGuard {}
+
+static UNPROTECTED: UnprotectedGuard = UnprotectedGuard(Guard { shield: None });
+
+/// Returns a reference to a dummy guard that allows unprotected access to [`Atomic`]s.
+/// Functions deferred on it are executed immediately.
+///
+/// # Safety
+///
+/// Loading and dereferencing pointers without a pinned guard is only sound
+/// if no other thread can concurrently modify or retire them.
+///
+/// [`Atomic`]: struct.Atomic.html
+pub unsafe fn unprotected() -> &'static Guard {
+    &UNPROTECTED.0
+}
+
+/// A guard that keeps the current thread pinned, see `crossbeam_epoch::Guard`.
+pub struct Guard {
+    shield: Option<ThinShield<'static>>,
+}
+
+impl Guard {
+    /// Defers the execution of a function until no thread can hold a reference
+    /// to an object unlinked while this guard is pinned.
+    pub fn defer<F, R>(&self, f: F)
+    where
+        F: FnOnce() -> R + Send + 'static,
+    {
+        match &self.shield {
+            Some(shield) => shield.retire(move || drop(f())),
+            None => drop(f()),
+        }
+    }
+
+    /// Like `Guard::defer` but without requiring the function to be `Send` or `'static`.
+    ///
+    /// # Safety
+    ///
+    /// The function may be executed by any thread and must not access data
+    /// that may be dropped before it is executed.
+    pub unsafe fn defer_unchecked<F, R>(&self, f: F)
+    where
+        F: FnOnce() -> R,
+    {
+        match &self.shield {
+            Some(shield) => shield.retire_unchecked(move || drop(f())),
+            None => drop(f()),
+        }
+    }
+
+    /// Defers dropping the object `ptr` points to.
+    ///
+    /// # Safety
+    ///
+    /// The object must have been allocated by `Owned`, must be unreachable
+    /// for threads that pin after this call and must not be destroyed twice.
+    pub unsafe fn defer_destroy<T>(&self, ptr: Shared<'_, T>) {
+        let data = ptr.data;
+        self.defer_unchecked(move || drop(Owned::<T>::from_usize(data)));
+    }
+
+    /// Hands deferred functions buffered by the current thread off to the collector.
+    pub fn flush(&self) {
+        if let Some(shield) = &self.shield {
+            shield.flush();
+        }
+    }
+
+    /// Unpins and then immediately re-pins the thread.
+    pub fn repin(&mut self) {
+        if let Some(shield) = &mut self.shield {
+            shield.repin();
+        }
+    }
+
+    /// Temporarily unpins the thread, executes the given function and then re-pins the thread.
+    pub fn repin_after<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        match &mut self.shield {
+            Some(shield) => shield.repin_after(f),
+            None => f(),
+        }
+    }
+
+    fn load<T>(&self, atomic: &Inner<T>, ordering: Ordering) -> usize {
+        match &self.shield {
+            Some(shield) => atomic.load(ordering, shield).into_raw(),
+            None => atomic
+                .load(ordering, unsafe { crate::unprotected() })
+                .into_raw(),
+        }
+    }
+}
+
+impl fmt::Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Guard { .. }")
+    }
+}
+
+/// Types that can be stored in an [`Atomic`], that is [`Owned`] and [`Shared`].
+///
+/// [`Atomic`]: struct.Atomic.html
+/// [`Owned`]: struct.Owned.html
+/// [`Shared`]: struct.Shared.html
+pub trait Pointer<T> {
+    /// Returns the tagged pointer as an integer, giving up ownership.
+    fn into_usize(self) -> usize;
+
+    /// Constructs the pointer from a tagged pointer as an integer.
+    ///
+    /// # Safety
+    ///
+    /// The integer must have been created by `Pointer::into_usize` of the same type.
+    unsafe fn from_usize(data: usize) -> Self;
+}
+
+/// An owned heap allocated object with a tag, see `crossbeam_epoch::Owned`.
+pub struct Owned<T> {
+    data: usize,
+    _m0: PhantomData<Box<T>>,
+}
+
+impl<T> Owned<T> {
+    /// Allocates `value` on the heap.
+    pub fn new(value: T) -> Self {
+        Self::from(Box::new(value))
+    }
+
+    /// Constructs an `Owned` from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been allocated by `Box` and must not be owned elsewhere.
+    pub unsafe fn from_raw(raw: *mut T) -> Self {
+        Self::from_usize(raw as usize)
+    }
+
+    /// Converts the object into a `Box`, dropping the tag.
+    pub fn into_box(self) -> Box<T> {
+        let (raw, _) = decompose_tag::<T>(self.data);
+        mem::forget(self);
+        unsafe { Box::from_raw(raw) }
+    }
+
+    /// Converts the object into a `Shared` bound to the lifetime of a guard.
+    pub fn into_shared(self, _guard: &Guard) -> Shared<'_, T> {
+        unsafe { Shared::from_usize(self.into_usize()) }
+    }
+
+    /// Returns the tag stored in the pointer.
+    pub fn tag(&self) -> usize {
+        decompose_tag::<T>(self.data).1
+    }
+
+    /// Returns the same pointer with the tag set to `tag`, bits not fitting in the alignment of `T` are truncated.
+    pub fn with_tag(self, tag: usize) -> Self {
+        let data = self.into_usize();
+        unsafe { Self::from_usize(compose_tag::<T>(data, tag)) }
+    }
+}
+
+impl<T> Pointer<T> for Owned<T> {
+    fn into_usize(self) -> usize {
+        let data = self.data;
+        mem::forget(self);
+        data
+    }
+
+    unsafe fn from_usize(data: usize) -> Self {
+        Self {
+            data,
+            _m0: PhantomData,
+        }
+    }
+}
+
+impl<T> From<Box<T>> for Owned<T> {
+    fn from(b: Box<T>) -> Self {
+        unsafe { Self::from_raw(Box::into_raw(b)) }
+    }
+}
+
+impl<T> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*decompose_tag::<T>(self.data).0 }
+    }
+}
+
+impl<T> DerefMut for Owned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *decompose_tag::<T>(self.data).0 }
+    }
+}
+
+impl<T> Drop for Owned<T> {
+    fn drop(&mut self) {
+        let (raw, _) = decompose_tag::<T>(self.data);
+        unsafe { drop(Box::from_raw(raw)) }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Owned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Owned")
+            .field("value", &**self)
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+/// A tagged pointer bound to the lifetime of a guard, see `crossbeam_epoch::Shared`.
+pub struct Shared<'g, T> {
+    data: usize,
+    _m0: PhantomData<(&'g (), *const T)>,
+}
+
+impl<'g, T> Shared<'g, T> {
+    /// Returns a null pointer.
+    pub fn null() -> Self {
+        unsafe { Self::from_usize(0) }
+    }
+
+    /// Returns true if the pointer is null, ignoring the tag.
+    pub fn is_null(&self) -> bool {
+        self.as_raw().is_null()
+    }
+
+    /// Returns the raw pointer without the tag.
+    pub fn as_raw(&self) -> *const T {
+        decompose_tag::<T>(self.data).0
+    }
+
+    /// Dereferences the pointer.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be non null and point to a live object.
+    pub unsafe fn deref(&self) -> &'g T {
+        &*self.as_raw()
+    }
+
+    /// Dereferences the pointer if it isn't null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be null or point to a live object.
+    pub unsafe fn as_ref(&self) -> Option<&'g T> {
+        self.as_raw().as_ref()
+    }
+
+    /// Takes ownership of the object.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be non null and no other thread may use it anymore.
+    pub unsafe fn into_owned(self) -> Owned<T> {
+        debug_assert!(!self.is_null(), "converting a null `Shared` into `Owned`");
+        Owned::from_usize(self.data)
+    }
+
+    /// Returns the tag stored in the pointer.
+    pub fn tag(&self) -> usize {
+        decompose_tag::<T>(self.data).1
+    }
+
+    /// Returns the same pointer with the tag set to `tag`, bits not fitting in the alignment of `T` are truncated.
+    pub fn with_tag(&self, tag: usize) -> Self {
+        unsafe { Self::from_usize(compose_tag::<T>(self.data, tag)) }
+    }
+}
+
+impl<T> Pointer<T> for Shared<'_, T> {
+    fn into_usize(self) -> usize {
+        self.data
+    }
+
+    unsafe fn from_usize(data: usize) -> Self {
+        Self {
+            data,
+            _m0: PhantomData,
+        }
+    }
+}
+
+impl<'g, T> Clone for Shared<'g, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'g, T> Copy for Shared<'g, T> {}
+
+impl<'g, T> PartialEq for Shared<'g, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl<'g, T> Eq for Shared<'g, T> {}
+
+impl<'g, T> fmt::Debug for Shared<'g, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("raw", &self.as_raw())
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+/// The error returned by a failed `Atomic::compare_exchange`.
+pub struct CompareExchangeError<'g, T, P>
+where
+    P: Pointer<T>,
+{
+    /// The value stored in the atomic.
+    pub current: Shared<'g, T>,
+
+    /// The value that was supposed to be stored, handed back to the caller.
+    pub new: P,
+}
+
+impl<'g, T, P> fmt::Debug for CompareExchangeError<'g, T, P>
+where
+    P: Pointer<T>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompareExchangeError")
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// An atomic tagged pointer, see `crossbeam_epoch::Atomic`.
+pub struct Atomic<T> {
+    inner: Inner<T>,
+    _m0: PhantomData<*const T>,
+}
+
+unsafe impl<T: Send + Sync> Send for Atomic<T> {}
+unsafe impl<T: Send + Sync> Sync for Atomic<T> {}
+
+impl<T> Atomic<T> {
+    /// Returns a null atomic pointer.
+    pub fn null() -> Self {
+        Self {
+            inner: Inner::null(),
+            _m0: PhantomData,
+        }
+    }
+
+    /// Allocates `value` on the heap and returns an atomic pointer to it.
+    pub fn new(value: T) -> Self {
+        Self::from(Owned::new(value))
+    }
+
+    fn from_usize(data: usize) -> Self {
+        Self {
+            inner: unsafe { Inner::from_raw(data) },
+            _m0: PhantomData,
+        }
+    }
+
+    fn shared<'s>(data: usize) -> crate::Shared<'s, T, NullTag, NullTag, 0, 0> {
+        unsafe { crate::Shared::from_raw(data) }
+    }
+
+    /// Loads the pointer.
+    pub fn load<'g>(&self, ordering: Ordering, guard: &'g Guard) -> Shared<'g, T> {
+        unsafe { Shared::from_usize(guard.load(&self.inner, ordering)) }
+    }
+
+    /// Stores a pointer, replacing the previous one without destroying it.
+    pub fn store<P>(&self, new: P, ordering: Ordering)
+    where
+        P: Pointer<T>,
+    {
+        self.inner.store(Self::shared(new.into_usize()), ordering);
+    }
+
+    /// Stores a pointer and returns the previous one.
+    pub fn swap<'g, P>(&self, new: P, ordering: Ordering, _guard: &'g Guard) -> Shared<'g, T>
+    where
+        P: Pointer<T>,
+    {
+        // the lifetime of the result is bound to the guard instead
+        let shield = unsafe { crate::unprotected() };
+        let old = self
+            .inner
+            .swap(Self::shared(new.into_usize()), ordering, shield);
+        unsafe { Shared::from_usize(old.into_raw()) }
+    }
+
+    /// Stores `new` if the current value is `current`.
+    /// On failure the current value and `new` are handed back.
+    pub fn compare_exchange<'g, P>(
+        &self,
+        current: Shared<'_, T>,
+        new: P,
+        success: Ordering,
+        failure: Ordering,
+        guard: &'g Guard,
+    ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T, P>>
+    where
+        P: Pointer<T>,
+    {
+        self.compare_exchange_impl(current, new, success, failure, guard, false)
+    }
+
+    /// Like `Atomic::compare_exchange` but may fail spuriously even if the current value is `current`,
+    /// which allows more efficient code on some platforms when used in a loop.
+    pub fn compare_exchange_weak<'g, P>(
+        &self,
+        current: Shared<'_, T>,
+        new: P,
+        success: Ordering,
+        failure: Ordering,
+        guard: &'g Guard,
+    ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T, P>>
+    where
+        P: Pointer<T>,
+    {
+        self.compare_exchange_impl(current, new, success, failure, guard, true)
+    }
+
+    fn compare_exchange_impl<'g, P>(
+        &self,
+        current: Shared<'_, T>,
+        new: P,
+        success: Ordering,
+        failure: Ordering,
+        _guard: &'g Guard,
+        weak: bool,
+    ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T, P>>
+    where
+        P: Pointer<T>,
+    {
+        // see comment on Atomic::swap
+        let shield = unsafe { crate::unprotected() };
+        let new = new.into_usize();
+        let (current, new_shared) = (Self::shared(current.data), Self::shared(new));
+
+        let result = if weak {
+            self.inner
+                .compare_exchange_weak(current, new_shared, success, failure, shield)
+        } else {
+            self.inner
+                .compare_exchange(current, new_shared, success, failure, shield)
+        };
+
+        match result {
+            Ok(_) => Ok(unsafe { Shared::from_usize(new) }),
+            Err(actual) => Err(CompareExchangeError {
+                current: unsafe { Shared::from_usize(actual.into_raw()) },
+                new: unsafe { P::from_usize(new) },
+            }),
+        }
+    }
+
+    /// Bitwise "and" of the tag with `value`, returning the previous pointer.
+    /// Only the bits of `value` that fit in the tag are used.
+    pub fn fetch_and<'g>(
+        &self,
+        value: usize,
+        ordering: Ordering,
+        _guard: &'g Guard,
+    ) -> Shared<'g, T> {
+        let previous = self
+            .inner
+            .data
+            .fetch_and(value | !low_bits::<T>(), ordering);
+        unsafe { Shared::from_usize(previous) }
+    }
+
+    /// Bitwise "or" of the tag with `value`, returning the previous pointer.
+    /// Only the bits of `value` that fit in the tag are used.
+    pub fn fetch_or<'g>(
+        &self,
+        value: usize,
+        ordering: Ordering,
+        _guard: &'g Guard,
+    ) -> Shared<'g, T> {
+        let previous = self.inner.data.fetch_or(value & low_bits::<T>(), ordering);
+        unsafe { Shared::from_usize(previous) }
+    }
+
+    /// Bitwise "xor" of the tag with `value`, returning the previous pointer.
+    /// Only the bits of `value` that fit in the tag are used.
+    pub fn fetch_xor<'g>(
+        &self,
+        value: usize,
+        ordering: Ordering,
+        _guard: &'g Guard,
+    ) -> Shared<'g, T> {
+        let previous = self.inner.data.fetch_xor(value & low_bits::<T>(), ordering);
+        unsafe { Shared::from_usize(previous) }
+    }
+
+    /// Takes ownership of the object the pointer points to.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be non null and no other thread may use it anymore.
+    pub unsafe fn into_owned(self) -> Owned<T> {
+        Owned::from_usize(self.inner.data.into_inner())
+    }
+}
+
+impl<T> From<Owned<T>> for Atomic<T> {
+    fn from(owned: Owned<T>) -> Self {
+        Self::from_usize(owned.into_usize())
+    }
+}
+
+impl<'g, T> From<Shared<'g, T>> for Atomic<T> {
+    fn from(shared: Shared<'g, T>) -> Self {
+        Self::from_usize(shared.into_usize())
+    }
+}
+
+impl<T> Default for Atomic<T> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T> fmt::Debug for Atomic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data = self.inner.data.load(Ordering::SeqCst);
+        let (raw, tag) = decompose_tag::<T>(data);
+
+        f.debug_struct("Atomic")
+            .field("raw", &raw)
+            .field("tag", &tag)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pin, unprotected, Atomic, Owned, Shared};
+    use crate::default_collector;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Node(u64);
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn tags() {
+        let guard = &pin();
+        let owned = Owned::new(7_u64).with_tag(5);
+        assert_eq!(owned.tag(), 5);
+        assert_eq!(*owned, 7);
+
+        let shared = owned.into_shared(guard);
+        assert_eq!(shared.tag(), 5);
+        assert_eq!(shared.with_tag(3).tag(), 3);
+        assert_eq!(shared.with_tag(3).as_raw(), shared.as_raw());
+        assert!(Shared::<u64>::null().with_tag(1).is_null());
+
+        unsafe {
+            assert_eq!(*shared.deref(), 7);
+            drop(shared.into_owned());
+        }
+    }
+
+    #[test]
+    fn compare_exchange_weak_and_fetch_tag() {
+        let atomic = Atomic::new(7_u64);
+        let guard = &pin();
+        let mut current = atomic.load(Ordering::Acquire, guard);
+
+        // weak exchanges may fail spuriously so they're retried in a loop
+        loop {
+            match atomic.compare_exchange_weak(
+                current,
+                current.with_tag(1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(_) => break,
+                Err(error) => current = error.current,
+            }
+        }
+
+        assert_eq!(atomic.fetch_or(6, Ordering::AcqRel, guard).tag(), 1);
+        assert_eq!(atomic.fetch_and(5, Ordering::AcqRel, guard).tag(), 7);
+        assert_eq!(atomic.fetch_xor(1, Ordering::AcqRel, guard).tag(), 5);
+
+        let shared = atomic.load(Ordering::Acquire, guard);
+        assert_eq!(shared.tag(), 4);
+        assert_eq!(shared.as_raw(), current.as_raw());
+
+        unsafe {
+            drop(atomic.into_owned());
+        }
+    }
+
+    #[test]
+    fn compare_exchange_and_defer_destroy() {
+        let atomic = Atomic::new(Node(1));
+
+        {
+            let guard = &pin();
+            let current = atomic.load(Ordering::Acquire, guard);
+            let stale = current.with_tag(1);
+
+            let error = atomic
+                .compare_exchange(
+                    stale,
+                    Owned::new(Node(2)),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .unwrap_err();
+
+            assert_eq!(error.current, current);
+            assert_eq!(error.new.0, 2);
+
+            let new = atomic
+                .compare_exchange(
+                    current,
+                    error.new,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .unwrap();
+
+            assert_eq!(unsafe { new.deref().0 }, 2);
+
+            unsafe {
+                guard.defer_destroy(current);
+            }
+
+            guard.flush();
+        }
+
+        // other tests may be pinned on the default collector concurrently
+        for _ in 0..1000 {
+            if DROPPED.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+
+            default_collector().try_collect_light();
+            thread::yield_now();
+        }
+
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+
+        unsafe {
+            let guard = unprotected();
+            let node = atomic.swap(Shared::null(), Ordering::Relaxed, guard);
+            guard.defer_destroy(node);
+        }
+
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,3 @@
+//! Compatibility layers easing migration from other reclamation libraries.
+
+pub mod crossbeam;
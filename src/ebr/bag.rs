@@ -1,9 +1,10 @@
-use super::epoch::Epoch;
+use super::{epoch::Epoch, unwind::Panics};
 use crate::deferred::Deferred;
 use tinyvec::ArrayVec;
 
 pub struct Bag {
-    deferred: ArrayVec<[(Deferred, Epoch); Self::SIZE]>,
+    deferred: ArrayVec<[(Deferred, Epoch, usize); Self::SIZE]>,
+    weight: usize,
 }
 
 impl Bag {
@@ -12,11 +13,14 @@ impl Bag {
     pub fn new() -> Self {
         Self {
             deferred: ArrayVec::new(),
+            weight: 0,
         }
     }
 
-    pub fn push(&mut self, deferred: Deferred, epoch: Epoch) {
-        self.deferred.push((deferred, epoch));
+    /// Pushes a deferred function retaining approximately `weight` bytes of memory.
+    pub fn push(&mut self, deferred: Deferred, epoch: Epoch, weight: usize) {
+        self.deferred.push((deferred, epoch, weight));
+        self.weight += weight;
     }
 
     pub fn is_full(&self) -> bool {
@@ -27,17 +31,26 @@ impl Bag {
         self.deferred.is_empty()
     }
 
-    pub fn try_process(&mut self, current_epoch: Epoch) {
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn try_process(&mut self, current_epoch: Epoch, panics: &mut Panics) {
         let collect_until = self
             .deferred
             .iter()
-            .filter(|(_, epoch)| epoch.has_passed(current_epoch, 1))
+            .filter(|(_, epoch, _)| epoch.has_passed(current_epoch, 1))
             .fuse()
             .count();
 
+        let weight = &mut self.weight;
+
         self.deferred
             .drain(..collect_until)
-            .for_each(|(deferred, _)| deferred.call());
+            .for_each(|(deferred, _, w)| {
+                *weight -= w;
+                panics.call(deferred);
+            });
     }
 
     fn last_epoch(&self) -> Epoch {
@@ -52,36 +65,61 @@ impl Bag {
 
     pub fn seal(self) -> SealedBag {
         let epoch = self.last_epoch();
-        let data = self.deferred.into_iter().map(|(x, _)| x).collect();
-        SealedBag::new(epoch, data)
+        let weight = self.weight;
+
+        let data = self
+            .deferred
+            .into_iter()
+            .map(|(deferred, _, weight)| (deferred, weight))
+            .collect();
+
+        SealedBag::new(epoch, data, weight)
     }
 }
 
 pub struct SealedBag {
     epoch: Epoch,
-    deferred: ArrayVec<[Deferred; Bag::SIZE]>,
+    deferred: ArrayVec<[(Deferred, usize); Bag::SIZE]>,
+    weight: usize,
 }
 
 impl SealedBag {
-    fn new(epoch: Epoch, deferred: ArrayVec<[Deferred; Bag::SIZE]>) -> Self {
-        Self { epoch, deferred }
+    fn new(
+        epoch: Epoch,
+        deferred: ArrayVec<[(Deferred, usize); Bag::SIZE]>,
+        weight: usize,
+    ) -> Self {
+        Self {
+            epoch,
+            deferred,
+            weight,
+        }
     }
 
     pub fn epoch(&self) -> Epoch {
         self.epoch
     }
 
-    pub fn len(&self) -> usize {
-        self.deferred.len()
+    pub fn is_empty(&self) -> bool {
+        self.deferred.is_empty()
+    }
+
+    /// The approximate amount of memory retained by the functions left in this bag.
+    pub fn weight(&self) -> usize {
+        self.weight
     }
 
-    pub unsafe fn run(self) -> usize {
-        let x = self.deferred.len();
+    /// Runs at most `budget` of the stored functions and returns how many were run.
+    /// Panics raised by the functions are recorded in `panics`.
+    pub unsafe fn run_partial(&mut self, budget: usize, panics: &mut Panics) -> usize {
+        let amount = budget.min(self.deferred.len());
+        let weight = &mut self.weight;
 
-        for deferred in self.deferred {
-            deferred.call();
-        }
+        self.deferred.drain(..amount).for_each(|(deferred, w)| {
+            *weight -= w;
+            panics.call(deferred);
+        });
 
-        x
+        amount
     }
 }
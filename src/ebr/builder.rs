@@ -0,0 +1,204 @@
+use super::{
+    global::Global,
+    policy::{AdvancePolicy, DEFAULT_POLICY},
+    Collector, MAX_GARBAGE_BYTES,
+};
+use crate::alloc::AllocRef;
+use crate::heap::Arc;
+use crate::tls2::TlsProvider;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use super::reclaimer::Reclaimer;
+
+#[cfg(feature = "std")]
+use crate::{alloc::GlobalAllocator, tls2::std_tls_provider};
+
+#[cfg(feature = "std")]
+use std::{any::Any, time::Duration};
+
+/// Determines what happens when a function is retired while the amount of garbage
+/// waiting for reclamation exceeds the hard cap configured with [`CollectorBuilder::garbage_cap`].
+///
+/// [`CollectorBuilder::garbage_cap`]: struct.CollectorBuilder.html#method.garbage_cap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapPolicy {
+    /// Spin and yield while attempting to collect garbage until the amount drops below the cap.
+    ///
+    /// The thread keeps waiting for as long as other threads hold back reclamation.
+    /// The shield used to retire the function keeps the current thread pinned which may itself
+    /// prevent reclamation, once that is the case the thread stops waiting shortly after and
+    /// retires the function anyway or fails in the case of `Shield::try_retire`.
+    Yield,
+
+    /// Make `Shield::try_retire` return the function back to the caller.
+    /// Since `Shield::retire` can't fail it behaves as with `CapPolicy::Yield`.
+    Fail,
+
+    /// Call a function with the amount of bytes retained by outstanding retired functions and then retire the function anyway.
+    Callback(fn(usize)),
+}
+
+/// A `CollectorBuilder` is used to configure a `Collector` before creating it.
+///
+/// ```
+/// use flize::Collector;
+/// use std::time::Duration;
+///
+/// let collector = Collector::builder()
+///     .max_garbage_bytes(64 * 1024)
+///     .background_reclaimer(Duration::from_millis(10))
+///     .build();
+/// ```
+pub struct CollectorBuilder {
+    pub(crate) allocator: AllocRef,
+    pub(crate) tls_provider: &'static dyn TlsProvider,
+    pub(crate) max_garbage_bytes: usize,
+    pub(crate) collection_budget: usize,
+    pub(crate) garbage_cap: Option<(usize, CapPolicy)>,
+    pub(crate) advance_policy: &'static dyn AdvancePolicy,
+    #[cfg(feature = "std")]
+    pub(crate) reclaim_interval: Option<Duration>,
+    #[cfg(feature = "std")]
+    pub(crate) panic_hook: Option<fn(Box<dyn Any + Send>)>,
+    #[cfg(feature = "std")]
+    pub(crate) shield_watchdog: Option<(Duration, fn(Duration))>,
+}
+
+impl CollectorBuilder {
+    /// Creates a builder with the default configuration
+    /// that will use the given allocator and thread local storage provider.
+    pub fn new(allocator: AllocRef, tls_provider: &'static dyn TlsProvider) -> Self {
+        Self {
+            allocator,
+            tls_provider,
+            max_garbage_bytes: MAX_GARBAGE_BYTES,
+            collection_budget: usize::MAX,
+            garbage_cap: None,
+            advance_policy: &DEFAULT_POLICY,
+            #[cfg(feature = "std")]
+            reclaim_interval: None,
+            #[cfg(feature = "std")]
+            panic_hook: None,
+            #[cfg(feature = "std")]
+            shield_watchdog: None,
+        }
+    }
+
+    /// Sets the approximate amount of memory retired functions may occupy
+    /// before a thread retiring garbage attempts to collect it.
+    pub fn max_garbage_bytes(mut self, bytes: usize) -> Self {
+        self.max_garbage_bytes = bytes;
+        self
+    }
+
+    /// Limits the amount of retired functions executed by a single automatic collection.
+    /// Garbage exceeding the budget is left for later collections, possibly on other threads.
+    /// This bounds the pause a thread may experience when dropping a shield or retiring a function.
+    ///
+    /// By default collections are unbounded.
+    pub fn collection_budget(mut self, budget: usize) -> Self {
+        self.collection_budget = budget;
+        self
+    }
+
+    /// Sets a hard cap on the approximate amount of memory retired functions waiting for reclamation may occupy.
+    /// Unlike `CollectorBuilder::max_garbage_bytes` which only triggers a collection attempt,
+    /// retiring a function while the cap is exceeded applies the given policy.
+    ///
+    /// Functions buffered in a shield before being handed off to the collector aren't counted.
+    /// By default there is no cap.
+    pub fn garbage_cap(mut self, bytes: usize, policy: CapPolicy) -> Self {
+        self.garbage_cap = Some((bytes, policy));
+        self
+    }
+
+    /// Sets the policy deciding when a participant exiting its critical section attempts
+    /// to advance the epoch. By default this is attempted at every check if there is garbage.
+    pub fn advance_policy(mut self, policy: &'static dyn AdvancePolicy) -> Self {
+        self.advance_policy = policy;
+        self
+    }
+
+    /// Spawns a thread owned by the collector that attempts to advance the epoch and execute
+    /// eligible retired functions once every `interval`.
+    ///
+    /// With the reclaimer enabled other threads don't execute retired functions on their own, they only hand
+    /// them off to the collector. Explicit calls to `Collector::try_collect_light` and `Collector::collect_with_budget`
+    /// still execute them on the calling thread and functions retired with `Shield::retire_local` are always executed
+    /// by the thread that retired them. Note that functions retired on a `ThinShield` are buffered per thread
+    /// and only become visible to the reclaimer once the buffer fills up or is flushed with `Shield::flush`.
+    ///
+    /// The thread is stopped and joined when the collector is dropped.
+    #[cfg(feature = "std")]
+    pub fn background_reclaimer(mut self, interval: Duration) -> Self {
+        self.reclaim_interval = Some(interval);
+        self
+    }
+
+    /// Sets a function receiving panics raised by retired functions.
+    ///
+    /// A panicking retired function doesn't stop the execution of the other functions collected
+    /// alongside it. Once the collection is complete the first panic is passed to the hook,
+    /// or resumed on the thread performing the collection if there is no hook.
+    /// The background reclaimer discards panics resumed on it and keeps running.
+    #[cfg(feature = "std")]
+    pub fn panic_hook(mut self, hook: fn(Box<dyn Any + Send>)) -> Self {
+        self.panic_hook = Some(hook);
+        self
+    }
+
+    /// Calls `hook` with the elapsed time when a closure passed to `Collector::with_shield`,
+    /// `Collector::with_full_shield` or `Local::with_shield` holds its shield for longer than `threshold`.
+    /// This helps catching shields accidentally held across long blocking calls.
+    ///
+    /// The watchdog is only active in builds with debug assertions enabled.
+    #[cfg(feature = "std")]
+    pub fn shield_watchdog(mut self, threshold: Duration, hook: fn(Duration)) -> Self {
+        self.shield_watchdog = Some((threshold, hook));
+        self
+    }
+
+    /// Creates the configured collector.
+    pub fn build(self) -> Collector {
+        let global = Arc::new(Global::new(&self), self.allocator.clone());
+
+        #[cfg(feature = "std")]
+        if let Some(interval) = self.reclaim_interval {
+            global.set_reclaimer(Reclaimer::spawn(Arc::clone(&global), interval));
+        }
+
+        Collector { global }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CollectorBuilder {
+    fn default() -> Self {
+        Self::new(AllocRef::new(GlobalAllocator), std_tls_provider())
+    }
+}
+
+impl fmt::Debug for CollectorBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("CollectorBuilder");
+        debug.field("max_garbage_bytes", &self.max_garbage_bytes);
+        debug.field("collection_budget", &self.collection_budget);
+        debug.field("garbage_cap", &self.garbage_cap);
+        debug.field("advance_policy", &self.advance_policy);
+
+        #[cfg(feature = "std")]
+        debug.field("reclaim_interval", &self.reclaim_interval);
+
+        #[cfg(feature = "std")]
+        debug.field("panic_hook", &self.panic_hook.is_some());
+
+        #[cfg(feature = "std")]
+        debug.field(
+            "shield_watchdog",
+            &self.shield_watchdog.map(|(threshold, _)| threshold),
+        );
+
+        debug.finish()
+    }
+}
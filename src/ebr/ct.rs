@@ -1,6 +1,8 @@
 use super::bag::{Bag, SealedBag};
 use super::epoch::{AtomicEpoch, Epoch};
 use super::global::Global;
+use super::pacer::Pacer;
+use super::unwind::Panics;
 use crate::barrier::light_barrier;
 use crate::deferred::Deferred;
 use crate::heap::Arc;
@@ -12,6 +14,7 @@ use core::sync::atomic::{fence, AtomicIsize, Ordering};
 pub struct CrossThread {
     epoch: CachePadded<AtomicEpoch>,
     shields: CachePadded<AtomicIsize>,
+    pacer: Pacer,
     bag: Mutex<Bag>,
 }
 
@@ -20,6 +23,7 @@ impl CrossThread {
         Self {
             epoch: CachePadded::new(AtomicEpoch::new(Epoch::ZERO)),
             shields: CachePadded::new(AtomicIsize::new(0)),
+            pacer: Pacer::new(),
             bag: Mutex::new(Bag::new()),
         }
     }
@@ -29,7 +33,7 @@ impl CrossThread {
     }
 
     unsafe fn should_advance(&self, global: &Global) -> bool {
-        global.should_advance()
+        self.pacer.should_advance(global, false)
     }
 
     pub(crate) unsafe fn enter(&self, global: &Global) {
@@ -58,19 +62,37 @@ impl CrossThread {
 
     unsafe fn finalize(&self, global: &Arc<Global>) {
         if self.should_advance(global) {
-            let local_state = Global::local_state(global);
-            let shield = local_state.shield();
-            global.try_cycle();
+            let local_state = global.local_state();
+            let shield = local_state.thin_shield();
+            let mut panics = Panics::new();
+            global.try_cycle(&mut panics);
             drop(shield);
+            global.resume(panics);
         }
     }
 
-    pub(crate) fn retire(&self, deferred: Deferred, epoch: Epoch) -> Option<SealedBag> {
+    /// Retires a function into the shared bag, returning the bag if it was sealed.
+    ///
+    /// Panics from processing old garbage are collected into `panics` so the caller can
+    /// hand off the sealed bag before resuming them.
+    pub(crate) fn retire(
+        &self,
+        deferred: Deferred,
+        weight: usize,
+        global: &Global,
+        panics: &mut Panics,
+    ) -> Option<SealedBag> {
+        let epoch = global.load_epoch_relaxed();
         let mut bag = self.bag.lock();
-        bag.try_process(epoch);
-        bag.push(deferred, epoch);
+        self.pacer.record_retire();
 
-        if bag.is_full() {
+        if global.collects_in_foreground() {
+            bag.try_process(epoch, panics);
+        }
+
+        bag.push(deferred, epoch, weight);
+
+        if bag.is_full() || bag.weight() > global.max_garbage_bytes() {
             Some(Self::i_flush(&mut bag))
         } else {
             None
@@ -0,0 +1,110 @@
+//! A process-wide collector available with the `global-collector` feature.
+
+use super::{local::LocalState, shield::ThinShield, Collector};
+use crate::lazy::Lazy;
+use crate::tls2;
+use std::cell::Cell;
+
+static COLLECTOR: Lazy<Collector> = Lazy::new(Collector::new);
+
+/// The participant of the current thread along with the thread id it belongs to.
+#[derive(Clone, Copy)]
+struct CachedState {
+    id: usize,
+    generation: usize,
+    local_state: &'static LocalState,
+}
+
+impl CachedState {
+    fn new() -> Self {
+        let global = &default_collector().global;
+        let id = global.thread_id();
+
+        Self {
+            id,
+            generation: tls2::generation(id),
+            local_state: global.local_state(),
+        }
+    }
+
+    /// The thread id is released when the thread local holding it is destroyed, after which
+    /// another thread may be handed the id and the participant along with it.
+    fn is_live(&self) -> bool {
+        tls2::generation(self.id) == self.generation
+    }
+}
+
+thread_local! {
+    static LOCAL_STATE: Cell<Option<CachedState>> = Cell::new(None);
+}
+
+/// Returns the process-wide default collector, creating it on first use.
+///
+/// flize generally avoids implicit global state but sharing a single collector
+/// lets crates interoperate without passing a `Collector` around.
+/// The collector is never dropped.
+pub fn default_collector() -> &'static Collector {
+    COLLECTOR.get()
+}
+
+/// Creates a shield on the default collector for the current thread.
+///
+/// The participant of the current thread is cached in a thread local
+/// so this avoids the lookup `Collector::thin_shield` performs.
+/// Once the thread id of the current thread has been released, for example in
+/// thread local destructors, this falls back to `Collector::thin_shield`.
+pub fn pin() -> ThinShield<'static> {
+    let cached = LOCAL_STATE.with(|cache| match cache.get() {
+        Some(cached) => cached,
+        None => {
+            let cached = CachedState::new();
+            cache.set(Some(cached));
+            cached
+        }
+    });
+
+    if cached.is_live() {
+        cached.local_state.thin_shield()
+    } else {
+        default_collector().thin_shield()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_collector, pin};
+    use crate::Shield;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn pin_default_collector() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+        let local = default_collector().local();
+
+        {
+            let shield = pin();
+            assert!(local.is_pinned());
+
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        assert!(!local.is_pinned());
+
+        // other tests may be pinned on the default collector concurrently
+        for _ in 0..1000 {
+            if EXECUTED.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+
+            default_collector().try_collect_light();
+            thread::yield_now();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 1);
+    }
+}
@@ -40,7 +40,7 @@ impl Epoch {
         now.data.saturating_sub(self.data) >= amount
     }
 
-    fn unique(self) -> u64 {
+    pub fn unique(self) -> u64 {
         self.data % Self::AMOUNT
     }
 }
@@ -0,0 +1,503 @@
+use super::{
+    bag::{Bag, SealedBag},
+    epoch::Epoch,
+    unwind::Panics,
+};
+use crate::deferred::Deferred;
+use crate::{alloc::AllocRef, heap::Box, CachePadded};
+use core::{
+    cell::{Cell, UnsafeCell},
+    iter, mem, ptr,
+    sync::atomic::{AtomicIsize, AtomicPtr, Ordering},
+};
+
+const LISTS: usize = Epoch::AMOUNT as usize;
+const SHARDS: usize = 16;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_LIST: BagList = BagList::new();
+
+struct Node {
+    bag: SealedBag,
+    next: *mut Node,
+}
+
+/// A lock-free stack of sealed bags. Bags are only ever removed by taking the whole stack
+/// at once so this doesn't suffer from the ABA problem.
+struct BagList {
+    head: AtomicPtr<Node>,
+}
+
+impl BagList {
+    const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn push(&self, node: *mut Node) {
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            unsafe {
+                (*node).next = head;
+            }
+
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(new_head) => head = new_head,
+            }
+        }
+    }
+
+    fn take(&self) -> *mut Node {
+        self.head.swap(ptr::null_mut(), Ordering::Acquire)
+    }
+}
+
+/// The bags and the amount of memory retained by the deferred functions retired into one shard.
+/// Bags that were eligible for execution but left over by a budgeted collection are kept in `pending`.
+struct Shard {
+    lists: [BagList; LISTS],
+    pending: BagList,
+    bytes: AtomicIsize,
+}
+
+impl Shard {
+    fn list(&self, epoch: Epoch) -> &BagList {
+        &self.lists[epoch.unique() as usize]
+    }
+
+    fn push_node(&self, node: *mut Node) {
+        let epoch = unsafe { (*node).bag.epoch() };
+        self.list(epoch).push(node);
+    }
+
+    fn bytes(&self) -> isize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Global storage for sealed bags waiting for the epoch to advance.
+///
+/// Bags are sorted into one of three lists depending on the epoch they were sealed in.
+/// When the global epoch is `n` every bag in the list for epoch `n + 1` was sealed at
+/// epoch `n - 2` or earlier so the whole list can be taken with a single swap and executed.
+///
+/// To avoid every retiring thread contending on the same cache lines the lists and the count
+/// of bytes retained by outstanding deferred functions are split into shards selected by thread id.
+/// Collection drains the eligible list of every shard.
+///
+/// A collection may be given a budget in which case it stops once it has executed that many
+/// deferred functions. Eligible bags it didn't get to are moved to a pending list of the shard
+/// which is drained first by the next collection regardless of the epoch.
+pub(crate) struct Garbage {
+    shards: [CachePadded<Shard>; SHARDS],
+    allocator: AllocRef,
+}
+
+impl Garbage {
+    pub(crate) fn new(allocator: AllocRef) -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: CachePadded<Shard> = CachePadded::new(Shard {
+            lists: [EMPTY_LIST, EMPTY_LIST, EMPTY_LIST],
+            pending: BagList::new(),
+            bytes: AtomicIsize::new(0),
+        });
+
+        Self {
+            shards: [EMPTY; SHARDS],
+            allocator,
+        }
+    }
+
+    fn shard(&self, id: usize) -> &Shard {
+        &self.shards[id % SHARDS]
+    }
+
+    /// Retires a bag into the shard belonging to the thread with the given id.
+    ///
+    /// Returns an approximation of the amount of bytes retained by outstanding deferred functions.
+    /// The total is only computed once the shard exceeds its fair share of `ceiling`,
+    /// before that the amount held by the shard itself is returned.
+    pub(crate) fn push(&self, id: usize, bag: SealedBag, ceiling: usize) -> usize {
+        let shard = self.shard(id);
+        let diff = bag.weight() as isize;
+
+        let node = Node {
+            bag,
+            next: ptr::null_mut(),
+        };
+
+        let (raw, _) = Box::into_raw(Box::new(node, self.allocator.clone()));
+        shard.push_node(raw);
+        let bytes = shard.bytes.fetch_add(diff, Ordering::Relaxed) + diff;
+
+        if bytes as usize > ceiling / SHARDS {
+            self.bytes()
+        } else {
+            bytes as usize
+        }
+    }
+
+    /// Sums up the amount of bytes retained by outstanding deferred functions over all shards.
+    pub(crate) fn bytes(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.bytes())
+            .sum::<isize>()
+            .max(0) as usize
+    }
+
+    /// Returns true if no shard holds any garbage.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.bytes() <= 0)
+    }
+
+    /// Executes bags that are safe to execute given that the global epoch is `epoch`
+    /// until `budget` deferred functions have been executed. Bags that are only partially executed
+    /// and bags not reached are left for a later call. Returns the amount of executed deferred functions.
+    /// Panics raised by deferred functions are recorded in `panics`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have observed the global epoch reaching `epoch`.
+    pub(crate) unsafe fn collect(&self, epoch: Epoch, budget: usize, panics: &mut Panics) -> usize {
+        let mut executed = 0;
+
+        for shard in self.shards.iter() {
+            if executed == budget {
+                break;
+            }
+
+            executed += self.collect_shard(shard, epoch, budget - executed, panics);
+        }
+
+        executed
+    }
+
+    unsafe fn collect_shard(
+        &self,
+        shard: &Shard,
+        epoch: Epoch,
+        budget: usize,
+        panics: &mut Panics,
+    ) -> usize {
+        let pending = shard.pending.take();
+        let mut executed = self.collect_nodes(shard, pending, epoch, budget, panics);
+
+        if executed < budget {
+            let eligible = shard.list(epoch.next()).take();
+            executed += self.collect_nodes(shard, eligible, epoch, budget - executed, panics);
+        }
+
+        executed
+    }
+
+    unsafe fn collect_nodes(
+        &self,
+        shard: &Shard,
+        mut node: *mut Node,
+        epoch: Epoch,
+        budget: usize,
+        panics: &mut Panics,
+    ) -> usize {
+        let mut executed = 0;
+        let mut released = 0;
+
+        while !node.is_null() {
+            let next = (*node).next;
+
+            // The list may have received a bag from a later epoch if the global epoch
+            // advanced after `epoch` was observed. Such bags aren't eligible yet and are put back.
+            if (*node).bag.epoch().has_passed(epoch, 2) {
+                let weight = (*node).bag.weight();
+                executed += (*node).bag.run_partial(budget - executed, panics);
+                released += weight - (*node).bag.weight();
+
+                if (*node).bag.is_empty() {
+                    drop(Box::from_raw(node, self.allocator.clone()));
+                } else {
+                    shard.pending.push(node);
+                }
+            } else {
+                shard.push_node(node);
+            }
+
+            node = next;
+        }
+
+        shard.bytes.fetch_sub(released as isize, Ordering::Relaxed);
+        executed
+    }
+}
+
+impl Drop for Garbage {
+    fn drop(&mut self) {
+        let lists = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.lists.iter().chain(iter::once(&shard.pending)));
+
+        for list in lists {
+            let mut node = list.take();
+
+            while !node.is_null() {
+                unsafe {
+                    let next = (*node).next;
+                    drop(Box::from_raw(node, self.allocator.clone()));
+                    node = next;
+                }
+            }
+        }
+    }
+}
+
+/// Thread-affine garbage of a single participant, executed by the owning thread itself.
+///
+/// Functions are buffered in a bag which is sealed and appended to a list once full or when
+/// collection is attempted. Bags in the list are sealed in epoch order so collection only has to look
+/// at the front of the list. No borrow is held while executing functions so they may retire more garbage.
+///
+/// The state of a thread is inherited by the next thread handed the same id once it exits.
+/// The owning thread is therefore recorded and functions queued by a previous owner are leaked
+/// instead of executed by its successor. Functions still queued when this is dropped are leaked
+/// as well since the dropping thread isn't necessarily the owning one.
+pub(crate) struct LocalGarbage {
+    bag: UnsafeCell<Bag>,
+    head: Cell<*mut Node>,
+    tail: Cell<*mut Node>,
+    owner: Cell<usize>,
+    allocator: AllocRef,
+}
+
+impl LocalGarbage {
+    pub(crate) fn new(allocator: AllocRef) -> Self {
+        Self {
+            bag: UnsafeCell::new(Bag::new()),
+            head: Cell::new(ptr::null_mut()),
+            tail: Cell::new(ptr::null_mut()),
+            owner: Cell::new(0),
+            allocator,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.get().is_null() && unsafe { (*self.bag.get()).is_empty() }
+    }
+
+    /// Records `owner` as the owning thread, leaking the functions queued by a previous owner.
+    /// Owners are identified by the generation of their thread id.
+    ///
+    /// # Safety
+    ///
+    /// This may only be called by the thread currently holding the id of this `LocalGarbage` instance.
+    pub(crate) unsafe fn claim(&self, owner: usize) {
+        if self.owner.replace(owner) != owner {
+            *self.bag.get() = Bag::new();
+            self.leak();
+        }
+    }
+
+    /// Unlinks and frees all sealed bags without executing their functions.
+    unsafe fn leak(&self) {
+        let mut node = self.head.replace(ptr::null_mut());
+        self.tail.set(ptr::null_mut());
+
+        while !node.is_null() {
+            let next = (*node).next;
+            drop(Box::from_raw(node, self.allocator.clone()));
+            node = next;
+        }
+    }
+
+    /// # Safety
+    ///
+    /// This may only be called by the thread owning this `LocalGarbage` instance.
+    pub(crate) unsafe fn push(&self, deferred: Deferred, epoch: Epoch) {
+        let bag = &mut *self.bag.get();
+        bag.push(deferred, epoch, 0);
+
+        if bag.is_full() {
+            self.seal();
+        }
+    }
+
+    unsafe fn seal(&self) {
+        let bag = mem::replace(&mut *self.bag.get(), Bag::new());
+
+        let node = Node {
+            bag: bag.seal(),
+            next: ptr::null_mut(),
+        };
+
+        let (raw, _) = Box::into_raw(Box::new(node, self.allocator.clone()));
+        let tail = self.tail.replace(raw);
+
+        if tail.is_null() {
+            self.head.set(raw);
+        } else {
+            (*tail).next = raw;
+        }
+    }
+
+    /// Executes all functions that are safe to execute given that the global epoch is `epoch`.
+    /// Returns the amount of executed functions. Panics raised by them are recorded in `panics`.
+    ///
+    /// # Safety
+    ///
+    /// This may only be called by the thread owning this `LocalGarbage` instance
+    /// and the caller must have observed the global epoch reaching `epoch`.
+    pub(crate) unsafe fn collect(&self, epoch: Epoch, panics: &mut Panics) -> usize {
+        if !(*self.bag.get()).is_empty() {
+            self.seal();
+        }
+
+        let mut executed = 0;
+
+        loop {
+            let node = self.head.get();
+
+            if node.is_null() || !(*node).bag.epoch().has_passed(epoch, 2) {
+                break;
+            }
+
+            self.head.set((*node).next);
+
+            if (*node).next.is_null() {
+                self.tail.set(ptr::null_mut());
+            }
+
+            let mut bag = Box::from_raw(node, self.allocator.clone()).move_out().bag;
+            executed += bag.run_partial(usize::MAX, panics);
+        }
+
+        executed
+    }
+}
+
+impl Drop for LocalGarbage {
+    fn drop(&mut self) {
+        unsafe {
+            self.leak();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Garbage;
+    use crate::alloc::{AllocRef, GlobalAllocator};
+    use crate::deferred::Deferred;
+    use crate::ebr::{bag::Bag, epoch::Epoch, unwind::Panics};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn epoch(n: u64) -> Epoch {
+        (0..n).fold(Epoch::ZERO, |epoch, _| epoch.next())
+    }
+
+    #[test]
+    fn collect_by_epoch() {
+        let allocator = AllocRef::new(GlobalAllocator);
+        let garbage = Garbage::new(allocator.clone());
+        let executed = AtomicUsize::new(0);
+
+        let push = |n| {
+            let mut bag = Bag::new();
+            let deferred = Deferred::new(
+                || {
+                    executed.fetch_add(1, Ordering::Relaxed);
+                },
+                &allocator,
+            );
+
+            bag.push(deferred, epoch(n), 1);
+            garbage.push(n as usize, bag.seal(), usize::MAX);
+        };
+
+        push(0);
+        push(1);
+        push(3);
+
+        unsafe {
+            assert_eq!(garbage.collect(epoch(1), usize::MAX, &mut Panics::new()), 0);
+
+            // the bag from epoch 3 shares a list with the bag from epoch 0 but is put back
+            assert_eq!(garbage.collect(epoch(2), usize::MAX, &mut Panics::new()), 1);
+            assert_eq!(executed.load(Ordering::Relaxed), 1);
+
+            assert_eq!(garbage.collect(epoch(3), usize::MAX, &mut Panics::new()), 1);
+            assert_eq!(garbage.collect(epoch(4), usize::MAX, &mut Panics::new()), 0);
+            assert_eq!(garbage.collect(epoch(5), usize::MAX, &mut Panics::new()), 1);
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 3);
+        assert!(garbage.is_empty());
+    }
+
+    #[test]
+    fn shard_accounting() {
+        let allocator = AllocRef::new(GlobalAllocator);
+        let garbage = Garbage::new(allocator.clone());
+
+        let push = |id, ceiling| {
+            let mut bag = Bag::new();
+            bag.push(Deferred::new(|| (), &allocator), Epoch::ZERO, 10);
+            bag.push(Deferred::new(|| (), &allocator), Epoch::ZERO, 20);
+            garbage.push(id, bag.seal(), ceiling)
+        };
+
+        assert_eq!(push(0, usize::MAX), 30);
+        assert_eq!(push(1, usize::MAX), 30);
+
+        // shard 0 is over its share of the ceiling so the total is computed
+        assert_eq!(push(0, 0), 90);
+        assert_eq!(garbage.bytes(), 90);
+
+        unsafe {
+            assert_eq!(garbage.collect(epoch(2), usize::MAX, &mut Panics::new()), 6);
+        }
+
+        assert!(garbage.is_empty());
+    }
+
+    #[test]
+    fn collect_with_budget() {
+        let allocator = AllocRef::new(GlobalAllocator);
+        let garbage = Garbage::new(allocator.clone());
+        let executed = AtomicUsize::new(0);
+
+        for id in 0..2 {
+            let mut bag = Bag::new();
+
+            for _ in 0..3 {
+                let deferred = Deferred::new(
+                    || {
+                        executed.fetch_add(1, Ordering::Relaxed);
+                    },
+                    &allocator,
+                );
+
+                bag.push(deferred, Epoch::ZERO, 8);
+            }
+
+            garbage.push(id, bag.seal(), usize::MAX);
+        }
+
+        unsafe {
+            // the first bag is only partially executed
+            assert_eq!(garbage.collect(epoch(2), 2, &mut Panics::new()), 2);
+            assert_eq!(garbage.bytes(), 32);
+
+            assert_eq!(garbage.collect(epoch(2), 3, &mut Panics::new()), 3);
+            assert_eq!(garbage.collect(epoch(2), 0, &mut Panics::new()), 0);
+            assert_eq!(garbage.collect(epoch(2), usize::MAX, &mut Panics::new()), 1);
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 6);
+        assert!(garbage.is_empty());
+    }
+}
@@ -1,58 +1,201 @@
 use super::{
     bag::SealedBag,
+    builder::{CapPolicy, CollectorBuilder},
     ct::CrossThread,
     epoch::{AtomicEpoch, Epoch},
-    local::{Local, LocalState},
-    shield::{FullShield, Shield, ThinShield},
+    garbage::Garbage,
+    local::{Local, LocalState, Participant},
+    policy::{AdvanceContext, AdvancePolicy, Clock},
+    shield::{FullShield, OwnedFullShield, Shield, ThinShield},
+    synchronize::WakerSlot,
+    unwind::Panics,
+    ADVANCE_PROBABILITY,
 };
 use crate::heap::Arc;
 use crate::{
-    alloc::AllocRef, barrier::strong_barrier, deferred::Deferred, queue::Queue, tls2::ThreadLocal,
-    tls2::TlsProvider, CachePadded,
-};
-use core::{
-    mem,
-    sync::atomic::{fence, AtomicIsize, Ordering},
+    alloc::AllocRef, backoff::Backoff, barrier::strong_barrier, queue::Queue, tls2,
+    tls2::ThreadLocal, tls2::TlsProvider, CachePadded,
 };
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+use core::time::Duration;
 
-fn deferred_ceiling(max_bytes: usize) -> usize {
-    max_bytes / mem::size_of::<Deferred>()
-}
+#[cfg(feature = "std")]
+use super::reclaimer::Reclaimer;
+
+#[cfg(feature = "std")]
+use crate::mutex::Mutex;
+
+#[cfg(feature = "std")]
+use super::unwind::PanicPayload;
+
+#[cfg(all(feature = "std", debug_assertions))]
+use std::time::Instant;
 
 pub(crate) struct Global {
     threads: ThreadLocal<Arc<LocalState>>,
-    deferred: Queue<SealedBag>,
+    deferred: Garbage,
     global_epoch: CachePadded<AtomicEpoch>,
-    deferred_amount: CachePadded<AtomicIsize>,
-    deferred_amount_ceiling: usize,
+    max_garbage_bytes: usize,
+    collect_in_foreground: bool,
+    collection_budget: usize,
+    garbage_cap: Option<(usize, CapPolicy)>,
+    advance_policy: &'static dyn AdvancePolicy,
+    buffer_timeout: Option<(&'static dyn Clock, Duration)>,
+    tls_provider: &'static dyn TlsProvider,
+    #[cfg(feature = "std")]
+    panic_hook: Option<fn(PanicPayload)>,
+    wakers: Queue<Arc<WakerSlot>>,
+    #[cfg(feature = "std")]
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    shield_watchdog: Option<(Duration, fn(Duration))>,
+    handles: AtomicUsize,
+    #[cfg(feature = "std")]
+    reclaimer: Mutex<Option<Reclaimer>>,
     pub(crate) ct: CrossThread,
     pub(crate) allocator: AllocRef,
 }
 
 impl Global {
-    pub(crate) fn new(
-        allocator: AllocRef,
-        tls_provider: &'static dyn TlsProvider,
-        max_garbage_bytes: usize,
-    ) -> Self {
+    pub(crate) fn new(config: &CollectorBuilder) -> Self {
+        let allocator = config.allocator.clone();
+        let tls_provider = config.tls_provider;
+
+        #[cfg(feature = "std")]
+        let collect_in_foreground = config.reclaim_interval.is_none();
+
+        #[cfg(not(feature = "std"))]
+        let collect_in_foreground = true;
+
         Self {
             threads: ThreadLocal::new(tls_provider, allocator.clone()),
-            deferred: Queue::new(allocator.clone()),
+            deferred: Garbage::new(allocator.clone()),
             global_epoch: CachePadded::new(AtomicEpoch::new(Epoch::ZERO)),
-            deferred_amount: CachePadded::new(AtomicIsize::new(0)),
-            deferred_amount_ceiling: deferred_ceiling(max_garbage_bytes),
+            max_garbage_bytes: config.max_garbage_bytes,
+            collect_in_foreground,
+            collection_budget: config.collection_budget,
+            garbage_cap: config.garbage_cap,
+            advance_policy: config.advance_policy,
+            buffer_timeout: config.advance_policy.buffer_timeout(),
+            tls_provider,
+            #[cfg(feature = "std")]
+            panic_hook: config.panic_hook,
+            wakers: Queue::new(allocator.clone()),
+            #[cfg(feature = "std")]
+            shield_watchdog: config.shield_watchdog,
+            handles: AtomicUsize::new(1),
+            #[cfg(feature = "std")]
+            reclaimer: Mutex::new(None),
             ct: CrossThread::new(),
             allocator,
         }
     }
 
-    pub(crate) fn local_state(this: &Arc<Self>) -> &Arc<LocalState> {
-        this.threads
-            .get(|| Arc::new(LocalState::new(Arc::clone(this)), this.allocator.clone()))
+    /// Identifies this collector by its address.
+    pub(crate) fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Hands ownership of the background reclaimer to the collector.
+    /// It is stopped once the last `Collector` handle is dropped.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_reclaimer(&self, reclaimer: Reclaimer) {
+        *self.reclaimer.lock() = Some(reclaimer);
+    }
+
+    /// Registers a new `Collector` handle, the caller must already own one.
+    pub(crate) fn clone_handle(&self) {
+        self.handles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registers a new `Collector` handle if any are left. Returns false if they're all gone.
+    pub(crate) fn upgrade_handle(&self) -> bool {
+        let mut handles = self.handles.load(Ordering::Relaxed);
+
+        loop {
+            if handles == 0 {
+                return false;
+            }
+
+            match self.handles.compare_exchange_weak(
+                handles,
+                handles + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => handles = actual,
+            }
+        }
+    }
+
+    /// Unregisters a `Collector` handle, stopping the background reclaimer if it was the last one.
+    pub(crate) fn release_handle(&self) {
+        if self.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            #[cfg(feature = "std")]
+            {
+                // the lock is released before the reclaimer is joined
+                let reclaimer = self.reclaimer.lock().take();
+                drop(reclaimer);
+            }
+        }
+    }
+
+    /// Returns false if retired functions are executed by a background reclaimer
+    /// in which case other threads should never execute them.
+    pub(crate) fn collects_in_foreground(&self) -> bool {
+        self.collect_in_foreground
+    }
+
+    /// The amount of memory retired functions may occupy before collection is attempted.
+    pub(crate) fn max_garbage_bytes(&self) -> usize {
+        self.max_garbage_bytes
+    }
+
+    /// The clock of the advance policy and how long participants may keep retired functions buffered, if limited.
+    pub(crate) fn buffer_timeout(&self) -> Option<(&dyn Clock, Duration)> {
+        self.buffer_timeout
+    }
+
+    pub(crate) fn has_garbage(&self) -> bool {
+        !self.deferred.is_empty()
+    }
+
+    /// The approximate amount of memory retained by retired functions handed off to the collector.
+    pub(crate) fn retained_bytes(&self) -> usize {
+        self.deferred.bytes()
+    }
+
+    /// The average amount of exits between advancement checks over all participants besides full shields.
+    pub(crate) fn average_advance_interval(&self) -> usize {
+        let (sum, count) = self.threads.iter().fold((0, 0), |(sum, count), state| {
+            (sum + state.advance_interval(), count + 1)
+        });
+
+        if count != 0 {
+            sum / count
+        } else {
+            ADVANCE_PROBABILITY
+        }
+    }
+
+    pub(crate) fn local_state(&self) -> &Arc<LocalState> {
+        self.threads
+            .get(|| Arc::new(LocalState::new(self), self.allocator.clone()))
+    }
+
+    #[cfg(feature = "global-collector")]
+    pub(crate) fn thread_id(&self) -> usize {
+        self.tls_provider.get()
+    }
+
+    /// Identifies the current thread for as long as it's alive, unlike the thread id alone
+    /// which may be handed to another thread once the current one exits.
+    pub(crate) fn thread_generation(&self) -> usize {
+        tls2::generation(self.tls_provider.get())
     }
 
     pub(crate) fn thin_shield(this: &Arc<Self>) -> ThinShield<'_> {
-        let local_state = Self::local_state(this);
+        let local_state = this.local_state();
         local_state.thin_shield()
     }
 
@@ -64,67 +207,224 @@ impl Global {
         FullShield::new(this)
     }
 
+    pub(crate) fn owned_full_shield(this: &Arc<Self>) -> OwnedFullShield {
+        unsafe {
+            this.ct.enter(this);
+        }
+
+        OwnedFullShield::new(Arc::clone(this))
+    }
+
+    /// Claims a participant released by a dropped handle or registers a new one.
+    pub(crate) fn participant(this: &Arc<Self>) -> Participant {
+        let released = this.threads.iter().find(|state| state.try_claim());
+
+        let local_state = released.unwrap_or_else(|| {
+            let local_state = LocalState::new_detached(this);
+            let local_state = Arc::new(local_state, this.allocator.clone());
+            this.threads.insert_detached(local_state)
+        });
+
+        Participant::new(Arc::clone(local_state), Arc::clone(this))
+    }
+
     pub(crate) fn local(this: &Arc<Self>) -> Local {
-        let local_state = Self::local_state(this);
-        Local::new(Arc::clone(&local_state))
+        let local_state = this.local_state();
+        Local::new(Arc::clone(local_state), Arc::clone(this))
     }
 
     pub(crate) fn load_epoch_relaxed(&self) -> Epoch {
         self.global_epoch.load(Ordering::Relaxed)
     }
 
+    pub(crate) fn load_epoch_acquire(&self) -> Epoch {
+        self.global_epoch.load(Ordering::Acquire)
+    }
+
+    /// Registers a waker slot to be woken the next time the epoch is advanced.
+    pub(crate) fn register_waker(&self, slot: Arc<WakerSlot>) {
+        self.wakers.push(slot);
+    }
+
+    /// Wakes all registered wakers. Must be called after advancing the epoch
+    /// and issuing a sequentially consistent fence.
+    fn wake_all(&self) {
+        self.wakers.drain().for_each(|slot| slot.wake());
+    }
+
+    /// Resumes the first panic raised by a deferred function during a collection
+    /// or passes it to the panic hook if one is configured.
+    ///
+    /// This must only be called once internal state is consistent again.
+    pub(crate) fn resume(&self, panics: Panics) {
+        #[cfg(feature = "std")]
+        panics.resume(self.panic_hook);
+
+        #[cfg(not(feature = "std"))]
+        drop(panics);
+    }
+
+    /// Runs a closure holding a scoped shield, calling the watchdog hook if it exceeds the threshold.
+    pub(crate) fn watch<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        #[cfg(all(feature = "std", debug_assertions))]
+        if let Some((threshold, hook)) = self.shield_watchdog {
+            let start = Instant::now();
+            let value = f();
+            let elapsed = start.elapsed();
+
+            if elapsed > threshold {
+                hook(elapsed);
+            }
+
+            return value;
+        }
+
+        f()
+    }
+
     pub(crate) fn retire_bag<'a, S>(&self, bag: SealedBag, _shield: &S)
     where
         S: Shield<'a>,
     {
-        let _epoch = self.global_epoch.load(Ordering::Relaxed);
-        let diff = bag.len() as isize;
-        self.deferred.push(bag);
-        let len = self.deferred_amount.fetch_add(diff, Ordering::Relaxed);
+        let id = self.tls_provider.get();
+        let bytes = self.deferred.push(id, bag, self.max_garbage_bytes);
+
+        if self.collect_in_foreground && bytes > self.max_garbage_bytes {
+            let mut panics = Panics::new();
+            let _ = self.try_cycle(&mut panics);
+            self.resume(panics);
+        }
+    }
+
+    /// Applies the configured cap policy if the amount of outstanding garbage exceeds the hard cap.
+    /// Returns false if the function about to be retired should be handed back to the caller,
+    /// this only happens if `fallible` is true.
+    ///
+    /// Some sort of shield must be held for the duration of this call, `pinned` is the epoch it's pinned in.
+    pub(crate) fn enforce_cap(&self, fallible: bool, pinned: Epoch) -> bool {
+        let (cap, policy) = match self.garbage_cap {
+            Some(garbage_cap) => garbage_cap,
+            None => return true,
+        };
 
-        if len as usize > self.deferred_amount_ceiling {
-            let _ = self.try_cycle();
+        let bytes = self.deferred.bytes();
+
+        if bytes < cap {
+            return true;
+        }
+
+        match policy {
+            CapPolicy::Callback(callback) => {
+                callback(bytes);
+                true
+            }
+
+            CapPolicy::Fail if fallible => false,
+            CapPolicy::Yield | CapPolicy::Fail => {
+                self.wait_for_reclamation(cap, pinned) || !fallible
+            }
         }
     }
 
-    pub(crate) fn should_advance(&self) -> bool {
-        self.deferred_amount.load(Ordering::Relaxed) > 0
+    /// Waits until the amount of outstanding garbage drops below `cap` for as long as that's possible
+    /// while the caller stays pinned in `pinned`. Returns true if it dropped below the cap.
+    fn wait_for_reclamation(&self, cap: usize, pinned: Epoch) -> bool {
+        let backoff = Backoff::new();
+        let mut panics = Panics::new();
+
+        let reclaimed = loop {
+            // The epoch can't advance more than once past the epoch the caller is pinned in.
+            // Once it has only the caller unpinning allows the remaining garbage to be reclaimed.
+            let blocked = pinned
+                .unpinned()
+                .has_passed(self.global_epoch.load(Ordering::Acquire), 1);
+
+            if self.collect_in_foreground {
+                let _ = self.try_cycle(&mut panics);
+            }
+
+            if self.deferred.bytes() < cap {
+                break true;
+            }
+
+            // the grace period gives a background reclaimer time to execute what's left
+            if blocked && backoff.is_completed() {
+                break false;
+            }
+
+            backoff.snooze();
+        };
+
+        self.resume(panics);
+        reclaimed
+    }
+
+    /// Consults the advancement policy when a participant checks whether to attempt advancement.
+    /// `exits` is the amount of exits since the participant last attempted advancement
+    /// and `local_garbage` indicates if it holds thread-affine garbage.
+    pub(crate) fn should_advance(&self, exits: usize, local_garbage: bool) -> bool {
+        let context = AdvanceContext::new(self, exits, local_garbage);
+        self.collect_in_foreground && self.advance_policy.should_advance(&context)
     }
 
     pub(crate) fn try_collect_light(this: &Arc<Self>) -> bool {
-        let local_state = Self::local_state(this);
-        let shield = local_state.shield();
-        let cycled = this.try_cycle();
+        let local_state = this.local_state();
+        let shield = local_state.thin_shield();
+        let mut panics = Panics::new();
+        let cycled = this.try_cycle(&mut panics);
         drop(shield);
+        this.resume(panics);
         cycled
     }
 
+    pub(crate) fn collect_with_budget(this: &Arc<Self>, budget: usize) -> usize {
+        let local_state = this.local_state();
+        let shield = local_state.thin_shield();
+
+        // garbage left behind by earlier budgeted collections may be executed
+        // even if the epoch can't be advanced right now
+        let (epoch, advanced) = match this.try_advance() {
+            Ok(epoch) => {
+                fence(Ordering::SeqCst);
+                (epoch, true)
+            }
+
+            Err(()) => (this.global_epoch.load(Ordering::Acquire), false),
+        };
+
+        let mut panics = Panics::new();
+        let executed = unsafe { this.internal_collect(epoch, budget, &mut panics) };
+
+        if advanced {
+            this.wake_all();
+        }
+
+        drop(shield);
+        this.resume(panics);
+        executed
+    }
+
     // Some sort of shield must be held for the duration of this call.
-    pub(crate) fn try_cycle(&self) -> bool {
+    // Panics raised by deferred functions are recorded in `panics` and must be resumed by the caller.
+    pub(crate) fn try_cycle(&self, panics: &mut Panics) -> bool {
         if let Ok(epoch) = self.try_advance() {
             fence(Ordering::SeqCst);
-            let cleaned = unsafe { self.internal_collect(epoch) };
-            self.deferred_amount
-                .fetch_sub(cleaned as isize, Ordering::Relaxed);
+            unsafe {
+                self.internal_collect(epoch, self.collection_budget, panics);
+            }
+
+            self.wake_all();
             true
         } else {
             false
         }
     }
 
-    unsafe fn internal_collect(&self, epoch: Epoch) -> usize {
-        let mut executed_amount = 0;
-
-        while let Some(sealed) = self.deferred.pop() {
-            if sealed.epoch().has_passed(epoch, 2) {
-                executed_amount += sealed.run();
-            } else {
-                self.deferred.push(sealed);
-                break;
-            }
-        }
-
-        executed_amount
+    unsafe fn internal_collect(&self, epoch: Epoch, budget: usize, panics: &mut Panics) -> usize {
+        self.deferred.collect(epoch, budget, panics)
     }
 
     fn try_advance(&self) -> Result<Epoch, ()> {
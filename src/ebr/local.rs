@@ -1,39 +1,78 @@
 use super::{
     bag::Bag,
     epoch::{AtomicEpoch, Epoch},
+    garbage::LocalGarbage,
     global::Global,
-    shield::{Shield, ThinShield},
-    ADVANCE_PROBABILITY,
+    pacer::Pacer,
+    shield::{unprotected, Shield, ThinShield},
+    unwind::Panics,
 };
 use crate::heap::Arc;
 use crate::{alloc::AllocRef, barrier::light_barrier, deferred::Deferred, CachePadded};
-use core::{cell::UnsafeCell, fmt, marker::PhantomData, mem, sync::atomic::Ordering};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    mem,
+    sync::atomic::{fence, AtomicBool, Ordering},
+    time::Duration,
+};
 
+/// The state of a participant, owned by the participant table of the collector.
+///
+/// The state only refers back to the collector without keeping it alive so the two don't form
+/// a cycle. Handles to a state, such as `Local` and `Participant`, hold on to the collector as well.
 pub(crate) struct LocalState {
-    global: Arc<Global>,
+    global: *const Global,
     epoch: CachePadded<AtomicEpoch>,
     shields: UnsafeCell<usize>,
-    advance_counter: UnsafeCell<usize>,
+    pacer: Pacer,
     bag: UnsafeCell<Bag>,
+    bag_started: UnsafeCell<Duration>,
+    local_garbage: LocalGarbage,
+    claimed: AtomicBool,
+    detached: bool,
 }
 
 impl LocalState {
-    pub(crate) fn new(global: Arc<Global>) -> Self {
+    pub(crate) fn new(global: &Global) -> Self {
+        Self::with_detached(global, false)
+    }
+
+    /// Creates the state of a participant that isn't tied to a thread.
+    pub(crate) fn new_detached(global: &Global) -> Self {
+        Self::with_detached(global, true)
+    }
+
+    fn with_detached(global: &Global, detached: bool) -> Self {
+        let local_garbage = LocalGarbage::new(global.allocator.clone());
+
         Self {
             global,
             epoch: CachePadded::new(AtomicEpoch::new(Epoch::ZERO)),
             shields: UnsafeCell::new(0),
-            advance_counter: UnsafeCell::new(0),
+            pacer: Pacer::new(),
             bag: UnsafeCell::new(Bag::new()),
+            bag_started: UnsafeCell::new(Duration::from_secs(0)),
+            local_garbage,
+            claimed: AtomicBool::new(true),
+            detached,
         }
     }
 
-    pub(crate) fn shield(&self) -> ThinShield {
-        ThinShield::new(self)
+    pub(crate) fn allocator(&self) -> &AllocRef {
+        &self.global().allocator
     }
 
-    pub(crate) fn allocator(&self) -> &AllocRef {
-        &self.global.allocator
+    pub(crate) fn global(&self) -> &Global {
+        // the collector owns the table holding this state and outlives it
+        unsafe { &*self.global }
+    }
+
+    /// The current amount of exits between advancement checks of this participant.
+    /// This may be called from any thread.
+    pub(crate) fn advance_interval(&self) -> usize {
+        self.pacer.interval()
     }
 
     /// This function loads the epoch without any ordering constraints.
@@ -47,15 +86,8 @@ impl LocalState {
     /// This modifies internal state.
     /// It may only be called from the thread owning this `LocalState` instance.
     unsafe fn should_advance(&self) -> bool {
-        let advance_counter = &mut *self.advance_counter.get();
-        *advance_counter += 1;
-
-        if *advance_counter != ADVANCE_PROBABILITY {
-            false
-        } else {
-            *advance_counter = 0;
-            self.global.should_advance()
-        }
+        let local_garbage = !self.local_garbage.is_empty();
+        self.pacer.should_advance(self.global(), local_garbage)
     }
 
     /// Records the creation of one thin shield. A call to this
@@ -71,7 +103,7 @@ impl LocalState {
         *shields = previous_shields + 1;
 
         if previous_shields == 0 {
-            let global_epoch = self.global.load_epoch_relaxed();
+            let global_epoch = self.global().load_epoch_relaxed();
             let new_epoch = global_epoch.pinned();
             self.epoch.store(new_epoch, Ordering::Relaxed);
             light_barrier();
@@ -87,46 +119,185 @@ impl LocalState {
     /// This modifies internal state.
     /// It may only be called from the thread owning this `LocalState` instance.
     pub(crate) unsafe fn exit(&self) {
+        if self.leave() {
+            let mut panics = Panics::new();
+            self.finalize(&mut panics);
+            self.collect_local_garbage(&mut panics);
+            self.global().resume(panics);
+        }
+    }
+
+    /// Decrements the shield counter and unpins the participant if it was the last shield.
+    /// Returns true if the participant was unpinned.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called from the thread owning this `LocalState` instance.
+    unsafe fn leave(&self) -> bool {
         let shields = &mut *self.shields.get();
         let previous_shields = *shields;
         *shields = previous_shields - 1;
 
         if previous_shields == 1 {
             self.epoch.store(Epoch::ZERO, Ordering::Relaxed);
-            self.finalize();
+            true
+        } else {
+            false
         }
     }
 
+    /// Panics raised by deferred functions are recorded in `panics` so that
+    /// the shield counter is restored before they are resumed.
+    ///
     /// # Safety
     ///
     /// This modifies internal state.
     /// It may only be called from the thread owning this `LocalState` instance.
-    unsafe fn finalize(&self) {
+    unsafe fn finalize(&self, panics: &mut Panics) {
         let shields = &mut *self.shields.get();
+        let advance = self.should_advance();
+
+        // hand off buffered garbage so it's executed even if this thread goes idle
+        if advance || self.is_bag_stale() {
+            self.hand_off_bag();
+        }
 
-        if self.should_advance() {
+        if advance {
             *shields += 1;
-            let _ = self.global.try_cycle();
+            let _ = self.global().try_cycle(panics);
             *shields -= 1;
         }
     }
 
+    /// Returns true if the bag holds functions that have been buffered for at least
+    /// as long as the advance policy allows.
+    ///
+    /// # Safety
+    ///
+    /// It may only be called from the thread owning this `LocalState` instance.
+    unsafe fn is_bag_stale(&self) -> bool {
+        if (*self.bag.get()).is_empty() {
+            return false;
+        }
+
+        match self.global().buffer_timeout() {
+            Some((clock, timeout)) => clock
+                .now()
+                .checked_sub(*self.bag_started.get())
+                .map_or(false, |age| age >= timeout),
+
+            None => false,
+        }
+    }
+
+    /// Hands the bag off to the collector if it isn't empty.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called from the thread owning this `LocalState` instance.
+    unsafe fn hand_off_bag(&self) {
+        if !(*self.bag.get()).is_empty() {
+            // functions are only executed once the epochs they were retired in have passed
+            // so handing them off doesn't require this participant to be pinned
+            self.force_flush(unprotected());
+        }
+    }
+
+    /// Executes the thread-affine garbage of this participant that is safe to execute.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called from the thread owning this `LocalState` instance.
+    unsafe fn collect_local_garbage(&self, panics: &mut Panics) -> usize {
+        if self.local_garbage.is_empty() {
+            return 0;
+        }
+
+        self.local_garbage.claim(self.global().thread_generation());
+        let epoch = self.global().load_epoch_relaxed();
+        fence(Ordering::Acquire);
+        self.local_garbage.collect(epoch, panics)
+    }
+
+    /// Attempts to advance the epoch unless a background reclaimer does so and then executes
+    /// the thread-affine garbage of this participant that is safe to execute. Returns the amount of executed functions.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called from the thread owning this `LocalState` instance.
+    unsafe fn poll_local_garbage(&self) -> usize {
+        let mut panics = Panics::new();
+        self.enter();
+
+        // with a background reclaimer only the reclaimer executes shared garbage
+        if self.global().collects_in_foreground() {
+            let _ = self.global().try_cycle(&mut panics);
+        }
+
+        if self.leave() {
+            self.finalize(&mut panics);
+        }
+
+        let executed = self.collect_local_garbage(&mut panics);
+        self.global().resume(panics);
+        executed
+    }
+
+    /// Queues a thread-affine function. Since the participant of a `Participant` handle
+    /// may move between threads such functions are queued on the participant of the current thread instead.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called from the thread owning this `LocalState` instance.
+    pub(crate) unsafe fn retire_local(&self, deferred: Deferred) {
+        if self.detached {
+            self.global().local_state().retire_local(deferred);
+        } else {
+            self.local_garbage.claim(self.global().thread_generation());
+            let epoch = self.global().load_epoch_relaxed();
+            self.local_garbage.push(deferred, epoch);
+        }
+    }
+
     fn is_pinned(&self) -> bool {
         self.epoch.load(Ordering::Relaxed).is_pinned()
     }
 
-    pub(crate) fn retire<'a, S>(&self, deferred: Deferred, shield: &S)
+    pub(crate) fn retire<'a, S>(&self, deferred: Deferred, weight: usize, shield: &S)
     where
         S: Shield<'a>,
     {
-        let epoch = self.global.load_epoch_relaxed();
+        let epoch = self.global().load_epoch_relaxed();
         let bag = unsafe { &mut *self.bag.get() };
-        bag.try_process(epoch);
-        bag.push(deferred, epoch);
+        self.pacer.record_retire();
+
+        let mut panics = Panics::new();
+
+        if self.global().collects_in_foreground() {
+            bag.try_process(epoch, &mut panics);
+        }
 
-        if bag.is_full() {
+        if bag.is_empty() {
+            if let Some((clock, _)) = self.global().buffer_timeout() {
+                unsafe {
+                    *self.bag_started.get() = clock.now();
+                }
+            }
+        }
+
+        bag.push(deferred, epoch, weight);
+
+        // hand off heavy bags early so large retirements promptly trigger advancement
+        if bag.is_full() || bag.weight() > self.global().max_garbage_bytes() {
             self.force_flush(shield);
         }
+
+        self.global().resume(panics);
     }
 
     pub(crate) fn flush<'a, S>(&self, shield: &S)
@@ -146,7 +317,30 @@ impl LocalState {
     {
         let bag = unsafe { &mut *self.bag.get() };
         let sealed = mem::replace(bag, Bag::new()).seal();
-        self.global.retire_bag(sealed, shield);
+        self.global().retire_bag(sealed, shield);
+    }
+
+    /// Attempts to take ownership of a state released by a dropped `Participant`.
+    /// States belonging to threads are never released.
+    pub(crate) fn try_claim(&self) -> bool {
+        self.claimed
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Hands any buffered garbage off to the collector and allows the state to be claimed again.
+    ///
+    /// # Safety
+    ///
+    /// This modifies internal state.
+    /// It may only be called by the owner of this `LocalState` instance while it has no active shields.
+    unsafe fn release(&self) {
+        if !(*self.bag.get()).is_empty() {
+            let shield = self.thin_shield();
+            self.force_flush(&shield);
+        }
+
+        self.claimed.store(false, Ordering::Release);
     }
 
     pub(crate) fn thin_shield(&self) -> ThinShield<'_> {
@@ -159,6 +353,7 @@ impl LocalState {
     }
 }
 
+#[allow(clippy::non_send_fields_in_send_ty)]
 unsafe impl Send for LocalState {}
 unsafe impl Sync for LocalState {}
 
@@ -167,13 +362,16 @@ unsafe impl Sync for LocalState {}
 /// `Collector::shield` every time since it avoids a table lookup to find the correct `Local`.
 pub struct Local {
     local_state: Arc<LocalState>,
+    // dropped after the state since the collector owns the table holding it
+    _global: Arc<Global>,
     _m0: PhantomData<*mut ()>,
 }
 
 impl Local {
-    pub(crate) fn new(local_state: Arc<LocalState>) -> Self {
+    pub(crate) fn new(local_state: Arc<LocalState>, global: Arc<Global>) -> Self {
         Self {
             local_state,
+            _global: global,
             _m0: PhantomData,
         }
     }
@@ -187,6 +385,27 @@ impl Local {
     pub fn is_pinned(&self) -> bool {
         self.local_state.is_pinned()
     }
+
+    /// Creates a shield on this local and runs a closure with it.
+    /// The shield is dropped once the closure returns or panics.
+    pub fn with_shield<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&ThinShield<'_>) -> R,
+    {
+        let shield = self.thin_shield();
+        self.local_state.global().watch(|| f(&shield))
+    }
+
+    /// Attempts to advance the epoch and executes functions retired with `Shield::retire_local`
+    /// on this thread that are safe to execute. Returns the amount of executed functions.
+    ///
+    /// If the collector has a background reclaimer the epoch is left to it to advance.
+    ///
+    /// Such functions are otherwise only executed when this thread drops its last active shield.
+    pub fn poll_local_garbage(&self) -> usize {
+        // a `Local` is bound to the thread that created it
+        unsafe { self.local_state.poll_local_garbage() }
+    }
 }
 
 impl fmt::Debug for Local {
@@ -194,3 +413,255 @@ impl fmt::Debug for Local {
         f.pad("Local { .. }")
     }
 }
+
+/// A `Participant` is a participant in the epoch system that isn't tied to a thread.
+/// It has its own epoch and garbage buffer just like the participant belonging to each thread,
+/// so shields created from it are as cheap as thin shields of a `Local`.
+///
+/// Participants can be moved to other threads, which makes them useful for giving every task
+/// of an asynchronous executor its own participant instead of relying on the shared bookkeeping
+/// of full shields. Shields borrow the participant so it can only be moved while it has no active shields.
+///
+/// Dropping a participant hands its buffered garbage off to the collector and makes it available
+/// for reuse by the next call to `Collector::participant`.
+pub struct Participant {
+    local_state: Arc<LocalState>,
+    // dropped after the state since the collector owns the table holding it
+    _global: Arc<Global>,
+    _m0: PhantomData<*mut ()>,
+}
+
+impl Participant {
+    pub(crate) fn new(local_state: Arc<LocalState>, global: Arc<Global>) -> Self {
+        Self {
+            local_state,
+            _global: global,
+            _m0: PhantomData,
+        }
+    }
+
+    /// Creates a shield on this participant.
+    pub fn thin_shield(&self) -> ThinShield<'_> {
+        self.local_state.thin_shield()
+    }
+
+    /// Returns true if this participant has active shields and it's epoch is pinned.
+    pub fn is_pinned(&self) -> bool {
+        self.local_state.is_pinned()
+    }
+}
+
+impl Drop for Participant {
+    fn drop(&mut self) {
+        // shields borrow the participant so none can be active at this point
+        unsafe {
+            self.local_state.release();
+        }
+    }
+}
+
+// The state of a participant is only ever accessed through a single handle at a time.
+// Claiming and releasing it synchronizes with the previous owner.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for Participant {}
+
+impl fmt::Debug for Participant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Participant { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalState, Participant};
+    use crate::ebr::epoch::Epoch;
+    use crate::{Collector, Shield};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn participant_moves_between_threads() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+        let participant = collector.participant();
+
+        let participant = thread::spawn(move || {
+            let shield = participant.thin_shield();
+
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            drop(shield);
+            participant
+        })
+        .join()
+        .unwrap();
+
+        let shield = participant.thin_shield();
+        assert!(participant.is_pinned());
+
+        {
+            let shield = collector.thin_shield();
+
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        // the pinned participant keeps the garbage from being executed
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 0);
+        drop(shield);
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 1);
+
+        // the garbage buffered in the participant is handed off on drop
+        drop(participant);
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn participants_are_reused() {
+        let collector = Collector::new();
+        let first = collector.participant();
+        let second = collector.participant();
+        let state = |participant: &Participant| &*participant.local_state as *const LocalState;
+        assert_ne!(state(&first), state(&second));
+
+        let released = state(&first);
+        drop(first);
+
+        let third = collector.participant();
+        assert_eq!(state(&third), released);
+    }
+
+    #[test]
+    fn retire_local() {
+        let collector = Collector::new();
+        let local = collector.local();
+        let executed = Rc::new(Cell::new(0));
+
+        {
+            let shield = local.thin_shield();
+            let executed = Rc::clone(&executed);
+            shield.retire_local(move || executed.set(executed.get() + 1));
+        }
+
+        {
+            let shield = collector.full_shield();
+            let executed = Rc::clone(&executed);
+            shield.retire_local(move || executed.set(executed.get() + 1));
+        }
+
+        let mut polled = 0;
+
+        for _ in 0..Epoch::AMOUNT {
+            polled += local.poll_local_garbage();
+        }
+
+        assert_eq!(polled, 2);
+        assert_eq!(executed.get(), 2);
+        assert_eq!(Rc::strong_count(&executed), 1);
+    }
+
+    #[test]
+    fn poll_with_background_reclaimer() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .background_reclaimer(Duration::from_secs(3600))
+            .build();
+
+        let local = collector.local();
+
+        {
+            let shield = local.thin_shield();
+
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        // shared garbage is left to the reclaimer
+        for _ in 0..Epoch::AMOUNT {
+            local.poll_local_garbage();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn retire_local_id_reuse() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+        let state = |local: &super::Local| &*local.local_state as *const LocalState as usize;
+
+        let exited = {
+            let collector = collector.clone();
+
+            thread::spawn(move || {
+                let local = collector.local();
+                let shield = local.thin_shield();
+
+                shield.retire_local(|| {
+                    EXECUTED.fetch_add(1, Ordering::Relaxed);
+                });
+
+                state(&local)
+            })
+            .join()
+            .unwrap()
+        };
+
+        // other tests may grab the released id first
+        for _ in 0..100 {
+            let collector = collector.clone();
+
+            let inherited = thread::spawn(move || {
+                let local = collector.local();
+
+                if state(&local) != exited {
+                    return false;
+                }
+
+                for _ in 0..Epoch::AMOUNT {
+                    local.poll_local_garbage();
+                }
+
+                true
+            })
+            .join()
+            .unwrap();
+
+            if inherited {
+                // the garbage of the exited thread is leaked instead of executed here
+                assert_eq!(EXECUTED.load(Ordering::Relaxed), 0);
+                return;
+            }
+        }
+
+        panic!("the thread id was never reused");
+    }
+}
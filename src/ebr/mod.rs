@@ -1,12 +1,39 @@
 mod bag;
+mod builder;
 mod ct;
+#[cfg(feature = "global-collector")]
+mod default;
 mod epoch;
+mod garbage;
 mod global;
 mod local;
+mod notify;
+mod pacer;
+mod policy;
+#[cfg(feature = "std")]
+mod reclaimer;
 mod shield;
+mod stats;
+mod synchronize;
+#[cfg(test)]
+mod testing;
+mod unwind;
+
+pub use builder::{CapPolicy, CollectorBuilder};
+pub use local::{Local, Participant};
+pub use notify::RetireHandle;
+pub use policy::{AdvanceContext, AdvancePolicy, Clock, CountPolicy, TimePolicy, VolumePolicy};
+pub use shield::{
+    unprotected, CowShield, FullShield, OwnedFullShield, Shield, ThinShield, UnprotectedShield,
+};
+pub use stats::Stats;
+pub use synchronize::Synchronize;
+
+#[cfg(feature = "std")]
+pub use policy::StdClock;
 
-pub use local::Local;
-pub use shield::{unprotected, CowShield, FullShield, Shield, ThinShield, UnprotectedShield};
+#[cfg(feature = "global-collector")]
+pub use default::{default_collector, pin};
 
 use crate::alloc::AllocRef;
 use crate::heap::Arc;
@@ -14,41 +41,106 @@ use crate::tls2::TlsProvider;
 use core::fmt;
 use global::Global;
 
-#[cfg(feature = "std")]
-use crate::tls2::std_tls_provider;
-
-#[cfg(feature = "std")]
-use crate::alloc::GlobalAllocator;
-
 const MAX_GARBAGE_BYTES: usize = 1024 * 1024;
 const ADVANCE_PROBABILITY: usize = 256;
 
 /// The `Collector` acts like the central bookkeeper, it stores all the retired functions that are queued
-/// for execution along with information on what each participant is doing, Participants are pretty much always
-/// thread specific as of now but cross-thread participants may be added in the future. This information can be used to determine approximately
+/// for execution along with information on what each participant is doing, Participants are usually
+/// thread specific but movable ones can be created with `Collector::participant`. This information can be used to determine approximately
 /// when a participant last was in in a critical section and relevant shield history. The collector
 /// uses this information to determine when it is safe to execute a retired function.
+///
+/// Creating a collector is cheap. The table of participants is allocated lazily in fixed size chunks
+/// as threads first interact with the collector so memory use scales with the amount of
+/// participating threads instead of the maximum amount of threads the collector could support.
+/// Locating the chunks only adds a pointer for every chunk of thread ids up to the highest id seen.
+///
+/// Collectors with non default settings, such as one executing garbage on a background thread,
+/// are created with a `CollectorBuilder`.
+///
+/// A `Collector` is a cheap handle to shared state and cloning it creates another handle
+/// to the same collector. Background tasks that shouldn't keep the collector usable
+/// can hold a `WeakCollector` instead. Once the last handle is dropped the background reclaimer,
+/// if any, is stopped.
+///
+/// The shared state, including the participant table, is freed once every handle is gone.
+/// `Local`s, `Participant`s, owned full shields and `WeakCollector`s keep it allocated as well.
+/// Garbage that hasn't been executed by then is leaked.
 pub struct Collector {
     global: Arc<Global>,
 }
 
+/// A `WeakCollector` is a handle to a collector that doesn't keep it usable,
+/// created with `Collector::downgrade`. Once every `Collector` handle has been dropped it can no longer
+/// be upgraded.
+///
+/// It still keeps the shared state of the collector allocated, along with the participant table,
+/// until it's dropped.
+pub struct WeakCollector {
+    global: Arc<Global>,
+}
+
+impl WeakCollector {
+    /// Attempts to create a `Collector` handle. Returns `None` if all `Collector` handles have been dropped.
+    pub fn upgrade(&self) -> Option<Collector> {
+        if self.global.upgrade_handle() {
+            Some(Collector {
+                global: Arc::clone(&self.global),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Clone for WeakCollector {
+    fn clone(&self) -> Self {
+        Self {
+            global: Arc::clone(&self.global),
+        }
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for WeakCollector {}
+unsafe impl Sync for WeakCollector {}
+
+impl fmt::Debug for WeakCollector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("WeakCollector { .. }")
+    }
+}
+
 impl Collector {
     #[cfg(feature = "std")]
     pub fn new() -> Self {
-        let allocator = AllocRef::new(GlobalAllocator);
-        let tls_provider = std_tls_provider();
-        Self::with_allocator_and_tls_provider(allocator, tls_provider)
+        Self::builder().build()
     }
 
     pub fn with_allocator_and_tls_provider(
         allocator: AllocRef,
         tls_provider: &'static dyn TlsProvider,
     ) -> Self {
-        Self {
-            global: Arc::new(
-                Global::new(allocator.clone(), tls_provider, MAX_GARBAGE_BYTES),
-                allocator,
-            ),
+        CollectorBuilder::new(allocator, tls_provider).build()
+    }
+
+    /// Creates a builder for configuring a collector using the global allocator
+    /// and the standard library thread local storage provider.
+    #[cfg(feature = "std")]
+    pub fn builder() -> CollectorBuilder {
+        CollectorBuilder::default()
+    }
+
+    /// Identifies this collector, shared by all handles to it.
+    #[cfg(all(feature = "branding", debug_assertions))]
+    pub(crate) fn id(&self) -> usize {
+        self.global.id()
+    }
+
+    /// Creates a `WeakCollector` handle to this collector.
+    pub fn downgrade(&self) -> WeakCollector {
+        WeakCollector {
+            global: Arc::clone(&self.global),
         }
     }
 
@@ -61,6 +153,47 @@ impl Collector {
         Global::full_shield(&self.global)
     }
 
+    /// Creates a shield on the appropriate local given the current thread and runs a closure with it.
+    /// The shield is dropped once the closure returns or panics so it can't be held for longer than intended.
+    ///
+    /// ```
+    /// use flize::{Atomic, Collector, NullTag};
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// let collector = Collector::new();
+    /// let atomic: Atomic<u64, NullTag, NullTag, 0, 0> = Atomic::null();
+    /// let is_null = collector.with_shield(|shield| atomic.load(Ordering::Acquire, shield).is_null());
+    /// assert!(is_null);
+    /// ```
+    pub fn with_shield<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&ThinShield<'_>) -> R,
+    {
+        let shield = self.thin_shield();
+        self.global.watch(|| f(&shield))
+    }
+
+    /// Like `Collector::with_shield` but runs the closure with a `FullShield`.
+    pub fn with_full_shield<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&FullShield<'_>) -> R,
+    {
+        let shield = self.full_shield();
+        self.global.watch(|| f(&shield))
+    }
+
+    /// Creates a full shield that doesn't borrow the collector and can therefore
+    /// be stored in `'static` contexts such as spawned tasks.
+    pub fn owned_full_shield(&self) -> OwnedFullShield {
+        Global::owned_full_shield(&self.global)
+    }
+
+    /// Creates a participant that isn't tied to the current thread and can be moved between threads.
+    /// Participants released by dropping their handle are reused before new ones are registered.
+    pub fn participant(&self) -> Participant {
+        Global::participant(&self.global)
+    }
+
     /// Get the local for the current thread.
     pub fn local(&self) -> Local {
         Global::local(&self.global)
@@ -68,9 +201,40 @@ impl Collector {
 
     /// Attempt to advance the epoch and collect garbage.
     /// Returns true if the epoch was cycled and garbage collected.
+    ///
+    /// Garbage is executed on the calling thread even if the collector has a background reclaimer.
     pub fn try_collect_light(&self) -> bool {
         Global::try_collect_light(&self.global)
     }
+
+    /// Attempt to advance the epoch and execute at most `budget` retired functions
+    /// that are safe to execute. Any remaining garbage is left for later calls or other threads.
+    /// Returns the amount of executed functions.
+    ///
+    /// This is useful for latency sensitive callers that want to bound the amount of work they absorb.
+    /// Like `Collector::try_collect_light` this executes garbage on the calling thread
+    /// even if the collector has a background reclaimer.
+    pub fn collect_with_budget(&self, budget: usize) -> usize {
+        Global::collect_with_budget(&self.global, budget)
+    }
+
+    /// Returns a future that resolves once the global epoch has advanced twice,
+    /// that is once every shield active at the time of the call has been dropped
+    /// and every function retired before the call is eligible for execution.
+    ///
+    /// Unlike spinning on `Collector::try_collect_light` this doesn't block the executor,
+    /// the future is woken whenever a thread advances the epoch.
+    pub fn synchronize_async(&self) -> Synchronize {
+        Synchronize::new(Arc::clone(&self.global))
+    }
+
+    /// Returns a snapshot of statistics about the state of the collector.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            retained_bytes: self.global.retained_bytes(),
+            advance_interval: self.global.average_advance_interval(),
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -80,6 +244,23 @@ impl Default for Collector {
     }
 }
 
+impl Clone for Collector {
+    fn clone(&self) -> Self {
+        self.global.clone_handle();
+
+        Self {
+            global: Arc::clone(&self.global),
+        }
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.global.release_handle();
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty)]
 unsafe impl Send for Collector {}
 unsafe impl Sync for Collector {}
 
@@ -88,3 +269,355 @@ impl fmt::Debug for Collector {
         f.pad("Collector { .. }")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{epoch::Epoch, testing::Counting, CapPolicy, Collector, Shield};
+    use crate::alloc::AllocRef;
+    use crate::deferred::Deferred;
+    use crate::tls2::std_tls_provider;
+    use core::mem;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn retire_and_collect() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::new();
+
+        {
+            let shield = collector.thin_shield();
+
+            for _ in 0..1000 {
+                shield.retire(|| {
+                    executed.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+
+            shield.flush();
+        }
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn background_reclaimer() {
+        let executed = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .background_reclaimer(Duration::from_millis(1))
+            .build();
+
+        {
+            let shield = collector.thin_shield();
+
+            for _ in 0..1000 {
+                shield.retire(|| {
+                    executed.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+
+            shield.flush();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while executed.load(Ordering::Relaxed) != 1000 {
+            assert!(Instant::now() < deadline, "reclaimer made no progress");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(collector);
+    }
+
+    #[test]
+    fn collect_with_budget() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::builder().collection_budget(10).build();
+
+        {
+            let shield = collector.thin_shield();
+
+            for _ in 0..100 {
+                shield.retire(|| {
+                    executed.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+
+            shield.flush();
+        }
+
+        let mut collected = 0;
+
+        for _ in 0..Epoch::AMOUNT {
+            let amount = collector.collect_with_budget(8);
+            assert!(amount <= 8);
+            collected += amount;
+        }
+
+        while collected != 100 {
+            let amount = collector.collect_with_budget(8);
+            assert!(amount > 0 && amount <= 8);
+            collected += amount;
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn garbage_cap_fail() {
+        let collector = Collector::builder()
+            .garbage_cap(64 * mem::size_of::<Deferred>(), CapPolicy::Fail)
+            .build();
+
+        let shield = collector.thin_shield();
+        let mut retired = 0;
+
+        // the shield keeps the thread pinned so nothing can be reclaimed
+        while shield.try_retire(|| ()).is_ok() {
+            retired += 1;
+            assert!(retired <= 1000, "cap was never enforced");
+        }
+
+        // bags are handed off to the collector once full so this is exactly two bags
+        assert_eq!(retired, 64);
+    }
+
+    #[test]
+    fn garbage_cap_yield() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+        static RETIRED: AtomicBool = AtomicBool::new(false);
+
+        let collector = Collector::builder()
+            .garbage_cap(64 * mem::size_of::<Deferred>(), CapPolicy::Yield)
+            .build();
+
+        let participant = collector.participant();
+        let pinned = participant.thin_shield();
+
+        {
+            let shield = collector.thin_shield();
+
+            for _ in 0..64 {
+                shield.retire(|| {
+                    EXECUTED.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        }
+
+        // the participant now lags behind and holds back the garbage at the cap
+        collector.try_collect_light();
+
+        let writer = {
+            let collector = collector.clone();
+
+            thread::spawn(move || {
+                let shield = collector.thin_shield();
+                shield.retire(|| ());
+                RETIRED.store(true, Ordering::Relaxed);
+                EXECUTED.load(Ordering::Relaxed)
+            })
+        };
+
+        // the writer isn't what blocks reclamation so it waits for the participant
+        thread::sleep(Duration::from_millis(50));
+        assert!(!RETIRED.load(Ordering::Relaxed));
+
+        drop(pinned);
+        assert_eq!(writer.join().unwrap(), 64);
+
+        // a writer holding back reclamation itself retires anyway
+        let shield = collector.thin_shield();
+
+        for _ in 0..100 {
+            shield.retire(|| ());
+        }
+    }
+
+    #[test]
+    fn garbage_cap_callback() {
+        static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+        fn callback(amount: usize) {
+            assert!(amount >= 64);
+            CALLED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let collector = Collector::builder()
+            .garbage_cap(
+                64 * mem::size_of::<Deferred>(),
+                CapPolicy::Callback(callback),
+            )
+            .build();
+
+        let shield = collector.thin_shield();
+
+        for _ in 0..100 {
+            shield.retire(|| ());
+        }
+
+        // every retirement after the collector reached the cap invokes the callback
+        assert_eq!(CALLED.load(Ordering::Relaxed), 100 - 64);
+    }
+
+    #[test]
+    fn weighted_retirement() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::new();
+
+        {
+            let shield = collector.thin_shield();
+
+            // heavier than the default garbage limit so the bag is handed off immediately
+            shield.retire_weighted(64 * 1024 * 1024, || {
+                executed.fetch_add(1, Ordering::Relaxed);
+            });
+
+            assert!(collector.stats().retained_bytes >= 64 * 1024 * 1024);
+        }
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 1);
+        assert_eq!(collector.stats().retained_bytes, 0);
+    }
+
+    #[test]
+    fn owned_full_shield() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+        let shield = collector.owned_full_shield();
+
+        let shield = thread::spawn(move || {
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+            shield
+        })
+        .join()
+        .unwrap();
+
+        // the shield is still active and prevents the epoch from advancing far enough
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 0);
+        drop(shield);
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn with_shield() {
+        static WATCHDOG: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .shield_watchdog(Duration::from_millis(10), |elapsed| {
+                assert!(elapsed > Duration::from_millis(10));
+                WATCHDOG.fetch_add(1, Ordering::Relaxed);
+            })
+            .build();
+
+        let local = collector.local();
+        assert!(collector.with_shield(|_| local.is_pinned()));
+        assert!(local.with_shield(|_| local.is_pinned()));
+        assert!(collector.with_full_shield(|_| !local.is_pinned()));
+        assert!(!local.is_pinned());
+
+        // the shield is dropped even if the closure panics
+        let panicking = || collector.with_shield(|_| panic!());
+        let result = panic::catch_unwind(AssertUnwindSafe(panicking));
+        assert!(result.is_err());
+        assert!(!local.is_pinned());
+
+        // only shields held longer than the threshold are reported
+        assert_eq!(WATCHDOG.load(Ordering::Relaxed), 0);
+        collector.with_shield(|_| thread::sleep(Duration::from_millis(20)));
+        let expected = if cfg!(debug_assertions) { 1 } else { 0 };
+        assert_eq!(WATCHDOG.load(Ordering::Relaxed), expected);
+    }
+
+    #[test]
+    fn clone_and_downgrade() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .background_reclaimer(Duration::from_millis(1))
+            .build();
+
+        let weak = collector.downgrade();
+        let clone = collector.clone();
+        drop(collector);
+
+        // the reclaimer keeps running as long as a handle is left
+        let upgraded = weak.upgrade().unwrap();
+        drop(clone);
+
+        {
+            let shield = upgraded.thin_shield();
+
+            shield.retire(|| {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while EXECUTED.load(Ordering::Relaxed) != 1 {
+            assert!(Instant::now() < deadline, "reclaimer made no progress");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(upgraded);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn freed_with_last_handle() {
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::with_allocator_and_tls_provider(
+            AllocRef::new(Counting(&LIVE)),
+            std_tls_provider(),
+        );
+
+        let local = collector.local();
+        let participant = collector.participant();
+        let weak = collector.downgrade();
+
+        {
+            let collector = collector.clone();
+            thread::spawn(move || drop(collector.thin_shield()))
+                .join()
+                .unwrap();
+        }
+
+        drop(collector);
+        drop(local.thin_shield());
+        drop(participant);
+        drop(weak);
+        assert_ne!(LIVE.load(Ordering::Relaxed), 0);
+
+        // the last handle frees the shared state along with the participant table
+        drop(local);
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::alloc::AllocRef;
+use crate::heap::Arc;
+use crate::mutex::Mutex;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+#[cfg(feature = "std")]
+use std::thread::{self, Thread};
+
+struct Waiters {
+    waker: Option<Waker>,
+    #[cfg(feature = "std")]
+    thread: Option<Thread>,
+}
+
+struct Completion {
+    done: AtomicBool,
+    waiters: Mutex<Waiters>,
+}
+
+/// Marks the `RetireHandle` it was created with as done when dropped.
+/// It is moved into the retired closure so that it is dropped once the closure has run,
+/// even if the closure panics.
+pub(crate) struct Notifier {
+    completion: Arc<Completion>,
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.completion.done.store(true, Ordering::Release);
+        let mut waiters = self.completion.waiters.lock();
+        let waker = waiters.waker.take();
+
+        #[cfg(feature = "std")]
+        if let Some(thread) = waiters.thread.take() {
+            thread.unpark();
+        }
+
+        // waking may run arbitrary code so the lock is released first
+        drop(waiters);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A `RetireHandle` is returned by `Shield::retire_with_notify` and completes
+/// once the retired function has been executed.
+///
+/// The handle can be polled with `RetireHandle::is_done`, waited on by blocking the current thread
+/// with `RetireHandle::wait` or awaited since it implements `Future`.
+///
+/// Note that retired functions are only executed once the collector gets around to it,
+/// a handle may therefore never complete if no thread interacts with the collector anymore.
+pub struct RetireHandle {
+    completion: Option<Arc<Completion>>,
+}
+
+impl RetireHandle {
+    pub(crate) fn new(allocator: &AllocRef) -> (Self, Notifier) {
+        let waiters = Waiters {
+            waker: None,
+            #[cfg(feature = "std")]
+            thread: None,
+        };
+
+        let completion = Completion {
+            done: AtomicBool::new(false),
+            waiters: Mutex::new(waiters),
+        };
+
+        let completion = Arc::new(completion, allocator.clone());
+        let notifier = Notifier {
+            completion: Arc::clone(&completion),
+        };
+
+        let handle = Self {
+            completion: Some(completion),
+        };
+
+        (handle, notifier)
+    }
+
+    /// Creates a handle for a function that has already been executed.
+    pub(crate) fn done() -> Self {
+        Self { completion: None }
+    }
+
+    /// Returns true if the retired function has been executed.
+    pub fn is_done(&self) -> bool {
+        self.completion
+            .as_ref()
+            .map_or(true, |completion| completion.done.load(Ordering::Acquire))
+    }
+
+    /// Blocks the current thread until the retired function has been executed.
+    ///
+    /// This must not be called while the current thread holds a shield
+    /// since that may prevent the function from ever being executed.
+    #[cfg(feature = "std")]
+    pub fn wait(self) {
+        if let Some(completion) = &self.completion {
+            completion.waiters.lock().thread = Some(thread::current());
+
+            // parking may wake up spuriously
+            while !completion.done.load(Ordering::Acquire) {
+                thread::park();
+            }
+        }
+    }
+}
+
+impl Future for RetireHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let completion = match &self.completion {
+            Some(completion) => completion,
+            None => return Poll::Ready(()),
+        };
+
+        if completion.done.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        completion.waiters.lock().waker = Some(cx.waker().clone());
+
+        // the function may have been executed before the waker was registered
+        if completion.done.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl fmt::Debug for RetireHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetireHandle")
+            .field("done", &self.is_done())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ebr::testing::counting_waker;
+    use crate::{unprotected, Collector, Shield};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+    use std::thread;
+
+    #[test]
+    fn wait_for_retirement() {
+        let collector = Collector::new();
+
+        let handle = {
+            let shield = collector.thin_shield();
+            let handle = shield.retire_with_notify(|| ());
+            shield.flush();
+            handle
+        };
+
+        assert!(!handle.is_done());
+
+        thread::spawn(move || {
+            for _ in 0..3 {
+                collector.try_collect_light();
+            }
+        });
+
+        handle.wait();
+    }
+
+    #[test]
+    fn poll_retirement() {
+        static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+        let waker = counting_waker(&WAKES);
+        let mut cx = Context::from_waker(&waker);
+
+        let mut handle = {
+            let shield = collector.full_shield();
+            let handle = shield.retire_with_notify(|| ());
+            shield.flush();
+            handle
+        };
+
+        assert_eq!(Pin::new(&mut handle).poll(&mut cx), Poll::Pending);
+
+        for _ in 0..3 {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(WAKES.load(Ordering::Relaxed), 1);
+        assert!(handle.is_done());
+        assert_eq!(Pin::new(&mut handle).poll(&mut cx), Poll::Ready(()));
+
+        let handle = unsafe { unprotected() }.retire_with_notify(|| ());
+        assert!(handle.is_done());
+    }
+}
@@ -0,0 +1,117 @@
+use super::{global::Global, ADVANCE_PROBABILITY};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const MIN_INTERVAL: usize = 16;
+const MAX_INTERVAL: usize = 4096;
+
+fn bump(counter: &AtomicUsize) -> usize {
+    let value = counter.load(Ordering::Relaxed) + 1;
+    counter.store(value, Ordering::Relaxed);
+    value
+}
+
+/// A `Pacer` decides how often a participant checks whether it should attempt to advance the epoch.
+///
+/// The interval between checks is measured in critical section exits and adapts to the activity
+/// of the participant. It is halved when the participant retired garbage since the last check or when
+/// garbage is piling up in the collector and doubled while there is no garbage at all.
+/// This keeps read-only phases cheap while write-heavy phases don't accumulate garbage between checks.
+///
+/// Counters are updated without read-modify-write operations since they're approximate anyway.
+pub(crate) struct Pacer {
+    interval: AtomicUsize,
+    exits: AtomicUsize,
+    exits_since_attempt: AtomicUsize,
+    retired: AtomicUsize,
+}
+
+impl Pacer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            interval: AtomicUsize::new(ADVANCE_PROBABILITY),
+            exits: AtomicUsize::new(0),
+            exits_since_attempt: AtomicUsize::new(0),
+            retired: AtomicUsize::new(0),
+        }
+    }
+
+    /// The current amount of exits between checks.
+    pub(crate) fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_retire(&self) {
+        bump(&self.retired);
+    }
+
+    /// Records an exit from a critical section and returns true if the participant
+    /// should attempt to advance the epoch. `local_garbage` indicates if the participant
+    /// holds thread-affine garbage waiting for the epoch to advance.
+    pub(crate) fn should_advance(&self, global: &Global, local_garbage: bool) -> bool {
+        if !global.collects_in_foreground() {
+            return false;
+        }
+
+        let exits_since_attempt = bump(&self.exits_since_attempt);
+
+        if bump(&self.exits) < self.interval() {
+            return false;
+        }
+
+        self.exits.store(0, Ordering::Relaxed);
+        self.adapt(global);
+
+        if global.should_advance(exits_since_attempt, local_garbage) {
+            self.exits_since_attempt.store(0, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn adapt(&self, global: &Global) {
+        let retired = self.retired.load(Ordering::Relaxed);
+        self.retired.store(0, Ordering::Relaxed);
+        let interval = self.interval();
+
+        let interval = if retired != 0 || global.retained_bytes() > global.max_garbage_bytes() / 2 {
+            interval / 2
+        } else if !global.has_garbage() {
+            interval * 2
+        } else {
+            interval
+        };
+
+        let interval = interval.max(MIN_INTERVAL).min(MAX_INTERVAL);
+        self.interval.store(interval, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_INTERVAL, MIN_INTERVAL};
+    use crate::{Collector, Shield};
+
+    #[test]
+    fn interval_adapts() {
+        let collector = Collector::new();
+        let initial = collector.stats().advance_interval;
+
+        // read-only phase
+        for _ in 0..100_000 {
+            drop(collector.thin_shield());
+        }
+
+        assert_eq!(collector.stats().advance_interval, MAX_INTERVAL);
+
+        // write-heavy phase
+        for _ in 0..10_000 {
+            let shield = collector.thin_shield();
+            shield.retire(|| ());
+        }
+
+        let interval = collector.stats().advance_interval;
+        assert!(interval < initial);
+        assert_eq!(interval, MIN_INTERVAL);
+    }
+}
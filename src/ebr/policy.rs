@@ -0,0 +1,345 @@
+//! Policies deciding when a participant attempts to advance the global epoch.
+
+use super::global::Global;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::lazy::Lazy;
+
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// A `Clock` is a monotonic source of time used by [`TimePolicy`].
+/// Because flize is designed to run on systems where the standard library doesn't exist
+/// we have this trait so you can use your own implementation.
+///
+/// The `now` method should return the time elapsed since some fixed but arbitrary point in time.
+/// Successive calls must never return a smaller value.
+///
+/// [`TimePolicy`]: struct.TimePolicy.html
+pub trait Clock: Sync + fmt::Debug {
+    fn now(&self) -> Duration;
+}
+
+impl<C> Clock for &C
+where
+    C: Clock + ?Sized,
+{
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// This clock is only usable with the `std` feature and uses `std::time::Instant`.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdClock;
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        static ORIGIN: Lazy<Instant> = Lazy::new(Instant::now);
+        ORIGIN.get().elapsed()
+    }
+}
+
+/// Information available to an [`AdvancePolicy`] when a participant exits its critical section.
+///
+/// [`AdvancePolicy`]: trait.AdvancePolicy.html
+pub struct AdvanceContext<'a> {
+    global: &'a Global,
+    exits: usize,
+    local_garbage: bool,
+}
+
+impl<'a> AdvanceContext<'a> {
+    pub(crate) fn new(global: &'a Global, exits: usize, local_garbage: bool) -> Self {
+        Self {
+            global,
+            exits,
+            local_garbage,
+        }
+    }
+
+    /// The amount of times the participant has exited its critical section
+    /// since it last attempted to advance the epoch.
+    pub fn exits(&self) -> usize {
+        self.exits
+    }
+
+    /// Returns true if garbage has been handed off to the collector and is waiting to be executed
+    /// or if the participant holds garbage retired with `Shield::retire_local`.
+    pub fn has_garbage(&self) -> bool {
+        self.local_garbage || self.global.has_garbage()
+    }
+
+    /// The approximate amount of memory in bytes retained by garbage waiting to be executed.
+    /// This is more expensive to compute than `AdvanceContext::has_garbage`.
+    pub fn retained_bytes(&self) -> usize {
+        self.global.retained_bytes()
+    }
+}
+
+impl<'a> fmt::Debug for AdvanceContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdvanceContext")
+            .field("exits", &self.exits)
+            .field("local_garbage", &self.local_garbage)
+            .finish()
+    }
+}
+
+/// An `AdvancePolicy` decides when a participant should attempt to advance the global epoch
+/// and execute garbage. Participants check with the policy when exiting their critical section,
+/// that is when their last shield is dropped. How often this happens adapts to the activity
+/// of the participant, varying between every 16 and every 4096 exits.
+///
+/// Policies are stored by reference and may keep state but that state will be shared by
+/// every collector using the same policy instance.
+pub trait AdvancePolicy: Sync + fmt::Debug {
+    fn should_advance(&self, context: &AdvanceContext<'_>) -> bool;
+
+    /// Returns the clock of this policy along with how long a participant may keep retired functions
+    /// buffered before handing them off to the collector. A participant exiting its critical section
+    /// hands off functions buffered for at least that long, even if it doesn't attempt advancement.
+    ///
+    /// Returns `None` by default, in which case buffered functions are only handed off once the buffer
+    /// fills up, when it's flushed with `Shield::flush` or when the participant attempts advancement.
+    /// This is read once when a collector is built.
+    fn buffer_timeout(&self) -> Option<(&dyn Clock, Duration)> {
+        None
+    }
+}
+
+/// Attempts advancement once a participant has exited its critical section a number of times
+/// since it last attempted advancement and there is garbage to execute.
+///
+/// The default policy is `CountPolicy::new(0)` which attempts advancement on every check
+/// if there is garbage.
+#[derive(Debug)]
+pub struct CountPolicy {
+    exits: usize,
+}
+
+impl CountPolicy {
+    pub const fn new(exits: usize) -> Self {
+        Self { exits }
+    }
+}
+
+impl AdvancePolicy for CountPolicy {
+    fn should_advance(&self, context: &AdvanceContext<'_>) -> bool {
+        context.exits() >= self.exits && context.has_garbage()
+    }
+}
+
+/// Attempts advancement whenever the garbage waiting to be executed retains at least a number of bytes.
+#[derive(Debug)]
+pub struct VolumePolicy {
+    bytes: usize,
+}
+
+impl VolumePolicy {
+    pub const fn new(bytes: usize) -> Self {
+        Self { bytes }
+    }
+}
+
+impl AdvancePolicy for VolumePolicy {
+    fn should_advance(&self, context: &AdvanceContext<'_>) -> bool {
+        context.has_garbage() && context.retained_bytes() >= self.bytes
+    }
+}
+
+/// Attempts advancement if there is garbage to execute and at least `interval` has elapsed
+/// since this policy last allowed an attempt. Time is read from a [`Clock`] on every check
+/// while garbage is present.
+///
+/// Functions retired on a `ThinShield` are buffered per participant. A participant hands them off
+/// to the collector when this policy allows it to attempt advancement or when it exits its critical section
+/// with functions buffered for at least `interval`, so they're executed even if it goes idle afterwards.
+/// A thread that goes idle right after retiring still holds on to them and should call `Shield::flush` first.
+///
+/// ```
+/// use flize::{Collector, StdClock, TimePolicy};
+/// use std::time::Duration;
+///
+/// static POLICY: TimePolicy<StdClock> = TimePolicy::new(Duration::from_millis(5));
+///
+/// let collector = Collector::builder()
+///     .advance_policy(&POLICY)
+///     .build();
+/// ```
+///
+/// [`Clock`]: trait.Clock.html
+#[derive(Debug)]
+pub struct TimePolicy<C> {
+    clock: C,
+    interval: Duration,
+    last: AtomicU64,
+}
+
+#[cfg(feature = "std")]
+impl TimePolicy<StdClock> {
+    /// Creates a policy using the standard library clock.
+    pub const fn new(interval: Duration) -> Self {
+        Self::with_clock(StdClock, interval)
+    }
+}
+
+impl<C> TimePolicy<C> {
+    /// Creates a policy reading time from the given clock.
+    pub const fn with_clock(clock: C, interval: Duration) -> Self {
+        Self {
+            clock,
+            interval,
+            last: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<C> AdvancePolicy for TimePolicy<C>
+where
+    C: Clock,
+{
+    fn should_advance(&self, context: &AdvanceContext<'_>) -> bool {
+        if !context.has_garbage() {
+            return false;
+        }
+
+        let now = self.clock.now().as_nanos() as u64;
+        let last = self.last.load(Ordering::Relaxed);
+
+        // only one participant gets to attempt advancement per interval
+        now.saturating_sub(last) >= self.interval.as_nanos() as u64
+            && self
+                .last
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    fn buffer_timeout(&self) -> Option<(&dyn Clock, Duration)> {
+        Some((&self.clock, self.interval))
+    }
+}
+
+pub(crate) static DEFAULT_POLICY: CountPolicy = CountPolicy::new(0);
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, TimePolicy};
+    use crate::{Collector, Shield};
+    use core::time::Duration;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[derive(Debug)]
+    struct ManualClock(AtomicU64);
+
+    impl ManualClock {
+        fn advance(&self, millis: u64) {
+            self.0.fetch_add(millis, Ordering::Relaxed);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    static CLOCK: ManualClock = ManualClock(AtomicU64::new(0));
+    static POLICY: TimePolicy<&ManualClock> =
+        TimePolicy::with_clock(&CLOCK, Duration::from_millis(10));
+
+    #[test]
+    fn time_policy() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::builder().advance_policy(&POLICY).build();
+
+        {
+            let shield = collector.thin_shield();
+
+            shield.retire(|| {
+                executed.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        // no time has passed so exits alone never attempt advancement
+        for _ in 0..10_000 {
+            drop(collector.thin_shield());
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 0);
+
+        // the bag is executed after the epoch has been advanced twice,
+        // exit often enough for the participant to check in between
+        for _ in 0..2 {
+            CLOCK.advance(10);
+
+            for _ in 0..4096 {
+                drop(collector.thin_shield());
+            }
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn idle_thread_garbage() {
+        static CLOCK: ManualClock = ManualClock(AtomicU64::new(0));
+        static POLICY: TimePolicy<&ManualClock> =
+            TimePolicy::with_clock(&CLOCK, Duration::from_millis(10));
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::builder().advance_policy(&POLICY).build();
+        let (retired_tx, retired_rx) = mpsc::channel();
+        let (unpin_tx, unpin_rx) = mpsc::channel();
+        let (idle_tx, idle_rx) = mpsc::channel();
+        let (exit_tx, exit_rx) = mpsc::channel::<()>();
+
+        let idle = {
+            let collector = collector.clone();
+
+            thread::spawn(move || {
+                let shield = collector.thin_shield();
+
+                shield.retire(|| {
+                    EXECUTED.fetch_add(1, Ordering::Relaxed);
+                });
+
+                retired_tx.send(()).unwrap();
+                unpin_rx.recv().unwrap();
+                drop(shield);
+
+                // the thread stays around without interacting with the collector
+                idle_tx.send(()).unwrap();
+                let _ = exit_rx.recv();
+            })
+        };
+
+        retired_rx.recv().unwrap();
+        CLOCK.advance(10);
+        unpin_tx.send(()).unwrap();
+        idle_rx.recv().unwrap();
+
+        // the bag was buffered for a full interval so it's handed off when the shield is dropped,
+        // exits on this thread then advance the epoch and execute it
+        for _ in 0..2 {
+            CLOCK.advance(10);
+
+            for _ in 0..4096 {
+                drop(collector.thin_shield());
+            }
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 1);
+        drop(exit_tx);
+        idle.join().unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use super::global::Global;
+use crate::heap::Arc;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        self,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Wrapper allowing the global state to be moved to the reclaimer thread.
+struct SendGlobal(Arc<Global>);
+
+// `Global` is designed to be shared between threads, `Collector` relies on this too.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for SendGlobal {}
+
+/// A thread that periodically attempts to advance the epoch and execute garbage
+/// on behalf of a collector. Dropping the handle stops the thread and waits for it to exit.
+pub(crate) struct Reclaimer {
+    stop: sync::Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Reclaimer {
+    pub(crate) fn spawn(global: Arc<Global>, interval: Duration) -> Self {
+        let stop = sync::Arc::new(AtomicBool::new(false));
+        let thread_stop = sync::Arc::clone(&stop);
+        let global = SendGlobal(global);
+
+        let handle = thread::Builder::new()
+            .name("flize-reclaimer".into())
+            .spawn(move || {
+                let global = global;
+                Self::run(&global.0, &thread_stop, interval);
+            })
+            .expect("failed to spawn reclaimer thread");
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    fn run(global: &Arc<Global>, stop: &AtomicBool, interval: Duration) {
+        loop {
+            let deadline = Instant::now() + interval;
+
+            // parking may wake up spuriously so keep going until the deadline has passed
+            loop {
+                if stop.load(Ordering::Acquire) {
+                    return;
+                }
+
+                let now = Instant::now();
+
+                if now >= deadline {
+                    break;
+                }
+
+                thread::park_timeout(deadline - now);
+            }
+
+            // Other threads never execute garbage so the reclaimer must outlive panicking functions.
+            // Without a panic hook the panic has already been reported by the standard panic hook.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| Global::try_collect_light(global)));
+        }
+    }
+}
+
+impl Drop for Reclaimer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(handle) = self.handle.take() {
+            // A retired function run by the reclaimer may drop the collector owning it.
+            // The thread can't join itself so it is left to exit on its own.
+            if handle.thread().id() != thread::current().id() {
+                handle.thread().unpark();
+                let _ = handle.join();
+            }
+        }
+    }
+}
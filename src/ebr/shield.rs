@@ -1,9 +1,18 @@
 use super::global::Global;
 use super::local::LocalState;
+use super::notify::RetireHandle;
+use super::unwind::Panics;
 use crate::deferred::Deferred;
 use crate::heap::Arc;
+
+#[cfg(feature = "std")]
+use crate::alloc::{AllocRef, GlobalAllocator};
 use core::fmt;
 use core::marker::PhantomData;
+use core::mem;
+
+/// The weight of functions retired without an explicit weight.
+const DEFAULT_WEIGHT: usize = mem::size_of::<Deferred>();
 
 /// Universal methods for any shield implementation.
 pub trait Shield<'a>: Clone + fmt::Debug {
@@ -31,18 +40,167 @@ pub trait Shield<'a>: Clone + fmt::Debug {
     /// Schedule a closure for execution once no shield may hold a reference
     /// to an object unlinked with the current shield.
     ///
+    /// The closure may be executed by any thread interacting with the collector
+    /// and is therefore required to be `Send`.
+    ///
     /// If this method is called from an [`unprotected`] shield, the closure will be executed
     /// immediately.
     ///
     /// [`unprotected`]: fn.unprotected.html
     fn retire<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'a;
+
+    /// Like `Shield::retire` but additionally specifies the approximate amount of memory in bytes
+    /// that is retained until the closure is executed, for example the size of a buffer it frees.
+    ///
+    /// The collector tracks the weight of outstanding closures against its garbage limits
+    /// so heavy retirements cause reclamation to be attempted sooner. Closures retired with
+    /// `Shield::retire` weigh as much as the collector needs to store them.
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately.
+    ///
+    /// Shields that don't override this ignore the weight and retire the closure with `Shield::retire`.
+    ///
+    /// [`unprotected`]: fn.unprotected.html
+    #[allow(unused_variables)]
+    fn retire_weighted<F>(&self, weight: usize, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.retire(f);
+    }
+
+    /// Like `Shield::retire` but hands the closure back if the hard garbage cap
+    /// of the collector is exceeded and reclamation doesn't catch up, see [`CapPolicy`].
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately.
+    ///
+    /// Shields that don't override this retire the closure with `Shield::retire` and never hand it back.
+    ///
+    /// [`CapPolicy`]: enum.CapPolicy.html
+    /// [`unprotected`]: fn.unprotected.html
+    fn try_retire<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.retire(f);
+        Ok(())
+    }
+
+    /// Like `Shield::retire` but without requiring the closure to be `Send`.
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately.
+    ///
+    /// # Safety
+    ///
+    /// The closure may be executed by any thread interacting with the collector.
+    /// The caller must ensure that this is sound even though the closure isn't `Send`,
+    /// for example by making sure it doesn't drop an `Rc` that is shared with other values.
+    ///
+    /// [`unprotected`]: fn.unprotected.html
+    unsafe fn retire_unchecked<F>(&self, f: F)
+    where
+        F: FnOnce() + 'a;
+
+    /// Like `Shield::retire` but returns a [`RetireHandle`] that completes once the closure
+    /// has been executed. This is useful when something has to wait for an object to actually
+    /// be destroyed, for example before unmapping memory the object references.
+    ///
+    /// The handle also completes if the closure panics. Closures retired on a `ThinShield`
+    /// are buffered per thread, `Shield::flush` hands them off to the collector right away.
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately and the returned handle is already complete.
+    ///
+    /// Shields that don't override this retire the closure with `Shield::retire` and allocate
+    /// the handle with the global allocator. Without the `std` feature there is no such allocator
+    /// and this method has to be implemented.
+    ///
+    /// [`RetireHandle`]: struct.RetireHandle.html
+    /// [`unprotected`]: fn.unprotected.html
+    #[cfg(feature = "std")]
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        let (handle, notifier) = RetireHandle::new(&AllocRef::new(GlobalAllocator));
+
+        self.retire(move || {
+            f();
+            drop(notifier);
+        });
+
+        handle
+    }
+
+    /// Like `Shield::retire` but returns a [`RetireHandle`] that completes once the closure
+    /// has been executed. This is useful when something has to wait for an object to actually
+    /// be destroyed, for example before unmapping memory the object references.
+    ///
+    /// The handle also completes if the closure panics. Closures retired on a `ThinShield`
+    /// are buffered per thread, `Shield::flush` hands them off to the collector right away.
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately and the returned handle is already complete.
+    ///
+    /// [`RetireHandle`]: struct.RetireHandle.html
+    /// [`unprotected`]: fn.unprotected.html
+    #[cfg(not(feature = "std"))]
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'a;
+
+    /// Like `Shield::retire` but the closure is only ever executed by the current thread
+    /// and is therefore not required to be `Send`.
+    ///
+    /// The closure is kept in a queue owned by the current thread instead of being handed off
+    /// to the collector. It is executed once the epoch has advanced sufficiently, either when
+    /// the thread drops its last active shield or when [`Local::poll_local_garbage`] is called.
+    /// If the thread stops interacting with the collector the closure may never be executed
+    /// and if it exits with the closure still queued the closure is leaked.
+    ///
+    /// Threads are told apart by the ids handed out by [`ThreadId`], a custom [`TlsProvider`]
+    /// must therefore allocate its ids with it.
+    ///
+    /// If this method is called from an [`unprotected`] shield, the closure will be executed
+    /// immediately.
+    ///
+    /// [`ThreadId`]: struct.ThreadId.html
+    /// [`TlsProvider`]: trait.TlsProvider.html
+    /// [`Local::poll_local_garbage`]: struct.Local.html#method.poll_local_garbage
+    /// [`unprotected`]: fn.unprotected.html
+    fn retire_local<F>(&self, f: F)
     where
         F: FnOnce() + 'a;
 
     /// Moves all deferred functions in the queue associated with the shield to the one associated with the collector.
     fn flush(&self);
+
+    /// Identifies the collector this shield belongs to, used to check that an `Atomic`
+    /// is only accessed with shields of the collector it belongs to. Returns 0 if the shield
+    /// isn't tied to a collector, which skips the check and is the default for other implementations.
+    #[doc(hidden)]
+    fn collector_id(&self) -> usize {
+        0
+    }
 }
 
+/// ```compile_fail
+///     use flize::{Collector, Shield};
+///     use std::rc::Rc;
+///
+///     let collector = Collector::new();
+///     let shield = collector.thin_shield();
+///     let rc = Rc::new(0);
+///     shield.retire(move || drop(rc));
+/// ```
+#[allow(unused)]
+struct RetireSendCompileFailTests;
+
 /// A `FullShield` is largely equivalent to `ThinShield` in terms of functionality.
 /// They're both shields with the same guarantees and can be user interchangeably.
 /// The major difference is that `FullShield` implements `Send` and `Sync` while
@@ -66,6 +224,26 @@ impl<'a> FullShield<'a> {
     pub(crate) fn new(global: &'a Arc<Global>) -> Self {
         Self { global }
     }
+
+    fn push_deferred<F>(&self, f: F, weight: usize)
+    where
+        F: FnOnce() + 'a,
+    {
+        let deferred = Deferred::new(f, &self.global.allocator);
+
+        let mut panics = Panics::new();
+        let sealed = self
+            .global
+            .ct
+            .retire(deferred, weight, self.global, &mut panics);
+
+        // hand off the sealed bag before resuming so it isn't lost while unwinding
+        if let Some(sealed) = sealed {
+            self.global.retire_bag(sealed, self);
+        }
+
+        self.global.resume(panics);
+    }
 }
 
 impl<'a> Shield<'a> for FullShield<'a> {
@@ -92,14 +270,69 @@ impl<'a> Shield<'a> for FullShield<'a> {
     }
 
     fn retire<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.retire_weighted(DEFAULT_WEIGHT, f);
+    }
+
+    fn retire_weighted<F>(&self, weight: usize, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.global
+            .enforce_cap(false, self.global.ct.load_epoch_relaxed());
+        self.push_deferred(f, weight);
+    }
+
+    fn try_retire<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        if self
+            .global
+            .enforce_cap(true, self.global.ct.load_epoch_relaxed())
+        {
+            self.push_deferred(f, DEFAULT_WEIGHT);
+            Ok(())
+        } else {
+            Err(f)
+        }
+    }
+
+    unsafe fn retire_unchecked<F>(&self, f: F)
+    where
+        F: FnOnce() + 'a,
+    {
+        self.global
+            .enforce_cap(false, self.global.ct.load_epoch_relaxed());
+        self.push_deferred(f, DEFAULT_WEIGHT);
+    }
+
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        let (handle, notifier) = RetireHandle::new(&self.global.allocator);
+
+        self.retire(move || {
+            f();
+            drop(notifier);
+        });
+
+        handle
+    }
+
+    fn retire_local<F>(&self, f: F)
     where
         F: FnOnce() + 'a,
     {
-        let epoch = self.global.load_epoch_relaxed();
+        let local_state = self.global.local_state();
         let deferred = Deferred::new(f, &self.global.allocator);
 
-        if let Some(sealed) = self.global.ct.retire(deferred, epoch) {
-            self.global.retire_bag(sealed, self);
+        // the state of the current thread is only ever accessed by this thread
+        unsafe {
+            local_state.retire_local(deferred);
         }
     }
 
@@ -108,6 +341,10 @@ impl<'a> Shield<'a> for FullShield<'a> {
             self.global.retire_bag(sealed, self);
         }
     }
+
+    fn collector_id(&self) -> usize {
+        self.global.id()
+    }
 }
 
 impl<'a> Clone for FullShield<'a> {
@@ -134,6 +371,169 @@ impl<'a> fmt::Debug for FullShield<'a> {
     }
 }
 
+/// An `OwnedFullShield` is a `FullShield` that owns a handle to the state of the collector
+/// instead of borrowing the `Collector`. This allows it to be stored in `'static` contexts
+/// such as spawned tasks or futures that are held across await points.
+///
+/// Since the shield may outlive the `Collector` it was created from it can only be used
+/// to retire `'static` closures. Apart from that it behaves exactly like a `FullShield`.
+///
+/// For documentation on functionality please check the documentation of the `Shield` trait.
+pub struct OwnedFullShield {
+    global: Arc<Global>,
+}
+
+impl OwnedFullShield {
+    pub(crate) fn new(global: Arc<Global>) -> Self {
+        Self { global }
+    }
+
+    fn push_deferred<F>(&self, f: F, weight: usize)
+    where
+        F: FnOnce() + 'static,
+    {
+        let deferred = Deferred::new(f, &self.global.allocator);
+
+        let mut panics = Panics::new();
+        let sealed = self
+            .global
+            .ct
+            .retire(deferred, weight, &self.global, &mut panics);
+
+        // hand off the sealed bag before resuming so it isn't lost while unwinding
+        if let Some(sealed) = sealed {
+            self.global.retire_bag(sealed, self);
+        }
+
+        self.global.resume(panics);
+    }
+}
+
+impl Shield<'static> for OwnedFullShield {
+    // see comment on FullShield::repin
+    fn repin(&mut self) {
+        unsafe {
+            self.global.ct.exit(&self.global);
+            self.global.ct.enter(&self.global);
+        }
+    }
+
+    fn repin_after<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // see comment on FullShield::repin
+        unsafe {
+            self.global.ct.exit(&self.global);
+            let value = f();
+            self.global.ct.enter(&self.global);
+            value
+        }
+    }
+
+    fn retire<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.retire_weighted(DEFAULT_WEIGHT, f);
+    }
+
+    fn retire_weighted<F>(&self, weight: usize, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.global
+            .enforce_cap(false, self.global.ct.load_epoch_relaxed());
+        self.push_deferred(f, weight);
+    }
+
+    fn try_retire<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self
+            .global
+            .enforce_cap(true, self.global.ct.load_epoch_relaxed())
+        {
+            self.push_deferred(f, DEFAULT_WEIGHT);
+            Ok(())
+        } else {
+            Err(f)
+        }
+    }
+
+    unsafe fn retire_unchecked<F>(&self, f: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        self.global
+            .enforce_cap(false, self.global.ct.load_epoch_relaxed());
+        self.push_deferred(f, DEFAULT_WEIGHT);
+    }
+
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (handle, notifier) = RetireHandle::new(&self.global.allocator);
+
+        self.retire(move || {
+            f();
+            drop(notifier);
+        });
+
+        handle
+    }
+
+    fn retire_local<F>(&self, f: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        let local_state = self.global.local_state();
+        let deferred = Deferred::new(f, &self.global.allocator);
+
+        // see comment on FullShield::retire_local
+        unsafe {
+            local_state.retire_local(deferred);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(sealed) = self.global.ct.flush() {
+            self.global.retire_bag(sealed, self);
+        }
+    }
+
+    fn collector_id(&self) -> usize {
+        self.global.id()
+    }
+}
+
+impl Clone for OwnedFullShield {
+    fn clone(&self) -> Self {
+        Global::owned_full_shield(&self.global)
+    }
+}
+
+impl Drop for OwnedFullShield {
+    fn drop(&mut self) {
+        // this is okay since we shall have called enter upon construction of this shield object
+        unsafe {
+            self.global.ct.exit(&self.global);
+        }
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for OwnedFullShield {}
+unsafe impl Sync for OwnedFullShield {}
+
+impl fmt::Debug for OwnedFullShield {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("OwnedFullShield { .. }")
+    }
+}
+
 /// A `ThinShield` locks an epoch and is needed to manipulate protected atomic pointers.
 /// It is a type level contract so that you are forces to acquire one before manipulating pointers.
 /// This reduces common mistakes drastically since incorrect code will now fail at compile time.
@@ -176,16 +576,84 @@ impl<'a> Shield<'a> for ThinShield<'a> {
     }
 
     fn retire<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.retire_weighted(DEFAULT_WEIGHT, f);
+    }
+
+    fn retire_weighted<F>(&self, weight: usize, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        self.local_state
+            .global()
+            .enforce_cap(false, self.local_state.load_epoch_relaxed());
+        let deferred = Deferred::new(f, self.local_state.allocator());
+        self.local_state.retire(deferred, weight, self);
+    }
+
+    fn try_retire<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        if self
+            .local_state
+            .global()
+            .enforce_cap(true, self.local_state.load_epoch_relaxed())
+        {
+            let deferred = Deferred::new(f, self.local_state.allocator());
+            self.local_state.retire(deferred, DEFAULT_WEIGHT, self);
+            Ok(())
+        } else {
+            Err(f)
+        }
+    }
+
+    unsafe fn retire_unchecked<F>(&self, f: F)
     where
         F: FnOnce() + 'a,
     {
+        self.local_state
+            .global()
+            .enforce_cap(false, self.local_state.load_epoch_relaxed());
         let deferred = Deferred::new(f, self.local_state.allocator());
-        self.local_state.retire(deferred, self);
+        self.local_state.retire(deferred, DEFAULT_WEIGHT, self);
+    }
+
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        let (handle, notifier) = RetireHandle::new(self.local_state.allocator());
+
+        self.retire(move || {
+            f();
+            drop(notifier);
+        });
+
+        handle
+    }
+
+    fn retire_local<F>(&self, f: F)
+    where
+        F: FnOnce() + 'a,
+    {
+        let deferred = Deferred::new(f, self.local_state.allocator());
+
+        // a `ThinShield` never leaves the thread owning its state
+        unsafe {
+            self.local_state.retire_local(deferred);
+        }
     }
 
     fn flush(&self) {
         self.local_state.flush(self);
     }
+
+    fn collector_id(&self) -> usize {
+        self.local_state.global().id()
+    }
 }
 
 impl<'a> Clone for ThinShield<'a> {
@@ -245,6 +713,43 @@ impl<'a> Shield<'a> for UnprotectedShield {
     }
 
     fn retire<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        f();
+    }
+
+    fn retire_weighted<F>(&self, _weight: usize, f: F)
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        f();
+    }
+
+    fn try_retire<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        f();
+        Ok(())
+    }
+
+    unsafe fn retire_unchecked<F>(&self, f: F)
+    where
+        F: FnOnce() + 'a,
+    {
+        f();
+    }
+
+    fn retire_with_notify<F>(&self, f: F) -> RetireHandle
+    where
+        F: FnOnce() + Send + 'a,
+    {
+        f();
+        RetireHandle::done()
+    }
+
+    fn retire_local<F>(&self, f: F)
     where
         F: FnOnce() + 'a,
     {
@@ -298,7 +803,7 @@ impl fmt::Debug for UnprotectedShield {
 ///     assert_eq!(s.as_ref_unchecked(), &7);
 ///
 ///     let ptr = a.load(Relaxed, unprotected).as_ptr();
-///     unprotected.retire(move || {
+///     unprotected.retire_unchecked(move || {
 ///         // This is executed immediately, thus `a` now holds an invalid pointer.
 ///         drop(Box::from_raw(ptr));    
 ///     });
@@ -353,3 +858,90 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Shield, ThinShield};
+    use crate::ebr::epoch::Epoch;
+    use crate::Collector;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A shield implemented outside of the collector that only provides the required methods.
+    #[derive(Clone, Debug)]
+    struct Forwarding<'a>(ThinShield<'a>);
+
+    impl<'a> Shield<'a> for Forwarding<'a> {
+        fn repin(&mut self) {
+            self.0.repin();
+        }
+
+        fn repin_after<F, R>(&mut self, f: F) -> R
+        where
+            F: FnOnce() -> R,
+        {
+            self.0.repin_after(f)
+        }
+
+        fn retire<F>(&self, f: F)
+        where
+            F: FnOnce() + Send + 'a,
+        {
+            self.0.retire(f);
+        }
+
+        unsafe fn retire_unchecked<F>(&self, f: F)
+        where
+            F: FnOnce() + 'a,
+        {
+            self.0.retire_unchecked(f);
+        }
+
+        fn retire_local<F>(&self, f: F)
+        where
+            F: FnOnce() + 'a,
+        {
+            self.0.retire_local(f);
+        }
+
+        fn flush(&self) {
+            self.0.flush();
+        }
+    }
+
+    #[test]
+    fn default_methods() {
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+
+        {
+            let shield = Forwarding(collector.thin_shield());
+
+            assert!(shield
+                .try_retire(|| {
+                    EXECUTED.fetch_add(1, Ordering::Relaxed);
+                })
+                .is_ok());
+
+            shield.retire_weighted(1024, || {
+                EXECUTED.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        let handle = {
+            let shield = Forwarding(collector.thin_shield());
+            let handle = shield.retire_with_notify(|| ());
+            shield.flush();
+            handle
+        };
+
+        for _ in 0..Epoch::AMOUNT {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 2);
+        assert!(handle.is_done());
+    }
+}
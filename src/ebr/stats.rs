@@ -0,0 +1,17 @@
+/// A snapshot of statistics about a collector obtained with `Collector::stats`.
+///
+/// The values are gathered without stopping other threads and are therefore approximate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The amount of memory in bytes retained by retired functions that have been handed off
+    /// to the collector and are waiting to be executed. Functions still buffered in shields aren't counted.
+    ///
+    /// See `Shield::retire_weighted` for how the memory retained by a function is determined.
+    pub retained_bytes: usize,
+
+    /// The average amount of critical section exits between checks whether to attempt advancing
+    /// the epoch over all participants besides full shields. Each participant adapts its interval to its activity,
+    /// checking more often while retiring garbage and less often while idle.
+    pub advance_interval: usize,
+}
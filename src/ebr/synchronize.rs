@@ -0,0 +1,174 @@
+use super::{epoch::Epoch, global::Global};
+use crate::heap::Arc;
+use crate::mutex::Mutex;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{fence, AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+/// The waker of a single `Synchronize`. The slot is queued in the collector at most once at a time
+/// and the waker is replaced in place when polled again so spurious polls don't grow the queue.
+pub(crate) struct WakerSlot {
+    waker: Mutex<Option<Waker>>,
+    queued: AtomicBool,
+}
+
+impl WakerSlot {
+    fn new() -> Self {
+        Self {
+            waker: Mutex::new(None),
+            queued: AtomicBool::new(false),
+        }
+    }
+
+    /// Stores the waker and returns true if the slot has to be queued in the collector.
+    fn register(&self, waker: &Waker) -> bool {
+        let mut current = self.waker.lock();
+
+        match &*current {
+            Some(current) if current.will_wake(waker) => (),
+            _ => *current = Some(waker.clone()),
+        }
+
+        drop(current);
+        !self.queued.swap(true, Ordering::AcqRel)
+    }
+
+    /// Wakes the stored waker after the slot has been removed from the queue of the collector.
+    pub(crate) fn wake(&self) {
+        // cleared first so a concurrent poll queues the slot again instead of being missed
+        self.queued.store(false, Ordering::Release);
+        let waker = self.waker.lock().take();
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A `Synchronize` is a future returned by `Collector::synchronize_async` that resolves once
+/// the global epoch has advanced twice since it was created. At that point every shield
+/// that was active when it was created has been dropped and every function retired before it was created
+/// is eligible for execution.
+///
+/// Wakers are woken whenever a thread successfully advances the epoch. Unless the collector
+/// uses a background reclaimer polling the future also attempts to advance the epoch so that it
+/// makes progress even if no other thread interacts with the collector.
+pub struct Synchronize {
+    global: Arc<Global>,
+    epoch: Epoch,
+    slot: Arc<WakerSlot>,
+}
+
+impl Synchronize {
+    pub(crate) fn new(global: Arc<Global>) -> Self {
+        let epoch = global.load_epoch_acquire();
+        let slot = Arc::new(WakerSlot::new(), global.allocator.clone());
+        Self {
+            global,
+            epoch,
+            slot,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.epoch.has_passed(self.global.load_epoch_acquire(), 2)
+    }
+}
+
+impl Future for Synchronize {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_complete() {
+            return Poll::Ready(());
+        }
+
+        if self.global.collects_in_foreground() && Global::try_collect_light(&self.global) {
+            if self.is_complete() {
+                return Poll::Ready(());
+            }
+
+            // we advanced the epoch ourselves and can try again right away
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        if self.slot.register(cx.waker()) {
+            self.global.register_waker(Arc::clone(&self.slot));
+        }
+
+        // pairs with the fence in `Global::try_cycle` so either we observe
+        // the advancement or the advancing thread observes the waker
+        fence(Ordering::SeqCst);
+
+        if self.is_complete() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Synchronize {
+    fn drop(&mut self) {
+        // the slot may stay queued until the next advancement, don't keep the waker alive with it
+        self.slot.waker.lock().take();
+    }
+}
+
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for Synchronize {}
+unsafe impl Sync for Synchronize {}
+
+impl fmt::Debug for Synchronize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Synchronize { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ebr::testing::counting_waker;
+    use crate::Collector;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+
+    #[test]
+    fn synchronize_async() {
+        static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::new();
+        let waker = counting_waker(&WAKES);
+        let mut cx = Context::from_waker(&waker);
+
+        // polling advances the epoch when nothing is in the way
+        let mut synchronize = collector.synchronize_async();
+        assert_eq!(Pin::new(&mut synchronize).poll(&mut cx), Poll::Pending);
+        assert_eq!(WAKES.load(Ordering::Relaxed), 1);
+        assert_eq!(Pin::new(&mut synchronize).poll(&mut cx), Poll::Ready(()));
+
+        // a pinned participant holds the future back until it unpins
+        let participant = collector.participant();
+        let shield = participant.thin_shield();
+        let mut synchronize = collector.synchronize_async();
+        assert_eq!(Pin::new(&mut synchronize).poll(&mut cx), Poll::Pending);
+
+        // spurious polls replace the registered waker instead of queueing more of them
+        for _ in 0..10 {
+            assert_eq!(Pin::new(&mut synchronize).poll(&mut cx), Poll::Pending);
+        }
+
+        assert_eq!(WAKES.load(Ordering::Relaxed), 2);
+
+        drop(shield);
+        assert!(collector.try_collect_light());
+        assert_eq!(WAKES.load(Ordering::Relaxed), 3);
+        assert_eq!(Pin::new(&mut synchronize).poll(&mut cx), Poll::Ready(()));
+    }
+}
@@ -0,0 +1,44 @@
+//! Helpers shared by the tests of the collector.
+
+use crate::alloc::{AllocRef, GlobalAllocator, Layout, VirtualAllocRef};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{RawWaker, RawWakerVTable, Waker};
+
+/// Forwards to the global allocator while counting the amount of live allocations.
+pub(crate) struct Counting(pub(crate) &'static AtomicUsize);
+
+unsafe impl VirtualAllocRef for Counting {
+    unsafe fn alloc(&self, layout: &Layout) -> *mut u8 {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        GlobalAllocator.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, layout: &Layout, ptr: *mut u8) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+        GlobalAllocator.dealloc(layout, ptr)
+    }
+
+    fn clone_untyped(&self) -> AllocRef {
+        AllocRef::new(Self(self.0))
+    }
+}
+
+/// Creates a waker that counts how many times it has been woken in `wakes`.
+pub(crate) fn counting_waker(wakes: &'static AtomicUsize) -> Waker {
+    fn raw(wakes: *const ()) -> RawWaker {
+        RawWaker::new(wakes, &VTABLE)
+    }
+
+    unsafe fn clone(wakes: *const ()) -> RawWaker {
+        raw(wakes)
+    }
+
+    unsafe fn wake(wakes: *const ()) {
+        (*(wakes as *const AtomicUsize)).fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe fn drop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+    unsafe { Waker::from_raw(raw(wakes as *const AtomicUsize as *const ())) }
+}
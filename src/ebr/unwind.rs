@@ -0,0 +1,211 @@
+use crate::deferred::Deferred;
+
+#[cfg(feature = "std")]
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+/// The payload of a panic raised by a deferred function.
+#[cfg(feature = "std")]
+pub(crate) type PanicPayload = Box<dyn Any + Send + 'static>;
+
+/// Records panics raised by deferred functions so that execution can continue with the remaining
+/// functions. The first panic is resumed by `Panics::resume` once internal state is consistent again.
+///
+/// Without the `std` feature panics can't be caught and unwind straight through the collector.
+pub struct Panics {
+    #[cfg(feature = "std")]
+    first: Option<PanicPayload>,
+}
+
+impl Panics {
+    pub(crate) fn new() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            first: None,
+        }
+    }
+
+    /// Executes a deferred function, recording the panic if it panics.
+    pub(crate) fn call(&mut self, deferred: Deferred) {
+        #[cfg(feature = "std")]
+        {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| deferred.call()));
+
+            if let Err(payload) = result {
+                // later panics are dropped, only the first one is resumed
+                if self.first.is_none() {
+                    self.first = Some(payload);
+                }
+            }
+        }
+
+        #[cfg(not(feature = "std"))]
+        deferred.call();
+    }
+
+    /// Resumes the first recorded panic or passes it to `hook` if one is given.
+    ///
+    /// If the thread is already unwinding the panic is passed to the hook or discarded
+    /// since panicking again would abort the process.
+    #[cfg(feature = "std")]
+    pub(crate) fn resume(self, hook: Option<fn(PanicPayload)>) {
+        if let Some(payload) = self.first {
+            match hook {
+                Some(hook) => hook(payload),
+                None if thread::panicking() => (),
+                None => panic::resume_unwind(payload),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Collector, Shield};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn panic_in_shield_exit() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::new();
+        let local = collector.local();
+
+        {
+            let shield = local.thin_shield();
+            let increment = || {
+                executed.fetch_add(1, Ordering::Relaxed);
+            };
+
+            shield.retire(increment);
+            shield.retire(|| panic!("deferred function panicked"));
+            shield.retire(increment);
+            shield.flush();
+        }
+
+        let mut panicked = 0;
+
+        for _ in 0..10_000 {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| drop(local.thin_shield())));
+
+            if result.is_err() {
+                panicked += 1;
+            }
+        }
+
+        // the remaining functions are executed and the shield count is restored
+        assert_eq!(panicked, 1);
+        assert_eq!(executed.load(Ordering::Relaxed), 2);
+        assert!(!local.is_pinned());
+
+        let shield = local.thin_shield();
+        assert!(local.is_pinned());
+        drop(shield);
+        assert!(!local.is_pinned());
+    }
+
+    #[test]
+    fn panic_hook() {
+        static PANICS: AtomicUsize = AtomicUsize::new(0);
+        static EXECUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .panic_hook(|_| {
+                PANICS.fetch_add(1, Ordering::Relaxed);
+            })
+            .build();
+
+        {
+            let shield = collector.full_shield();
+
+            for _ in 0..2 {
+                shield.retire(|| {
+                    EXECUTED.fetch_add(1, Ordering::Relaxed);
+                    panic!("deferred function panicked");
+                });
+            }
+
+            shield.flush();
+        }
+
+        for _ in 0..3 {
+            collector.try_collect_light();
+        }
+
+        // only the first panic of a collection is reported
+        assert_eq!(EXECUTED.load(Ordering::Relaxed), 2);
+        assert_eq!(PANICS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn panic_in_full_shield_retire() {
+        let executed = AtomicUsize::new(0);
+        let collector = Collector::new();
+
+        {
+            let shield = collector.full_shield();
+            shield.retire(|| panic!("deferred function panicked"));
+        }
+
+        for _ in 0..3 {
+            collector.try_collect_light();
+        }
+
+        // the panicking function is processed while retiring and the heavy retirement seals the bag
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let shield = collector.full_shield();
+
+            shield.retire_weighted((1 << 20) + 1, || {
+                executed.fetch_add(1, Ordering::Relaxed);
+            });
+        }));
+
+        assert!(result.is_err());
+
+        for _ in 0..3 {
+            collector.try_collect_light();
+        }
+
+        assert_eq!(executed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn panic_on_reclaimer() {
+        let executed = AtomicUsize::new(0);
+
+        let collector = Collector::builder()
+            .background_reclaimer(Duration::from_millis(1))
+            .build();
+
+        {
+            let shield = collector.full_shield();
+            shield.retire(|| panic!("deferred function panicked"));
+            shield.flush();
+        }
+
+        // the panic is resumed on the reclaimer before garbage retired afterwards is executed
+        thread::sleep(Duration::from_millis(20));
+
+        {
+            let shield = collector.full_shield();
+
+            shield.retire(|| {
+                executed.fetch_add(1, Ordering::Relaxed);
+            });
+
+            shield.flush();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while executed.load(Ordering::Relaxed) != 1 {
+            assert!(Instant::now() < deadline, "reclaimer stopped after a panic");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -29,7 +29,7 @@ impl<T> Arc<T> {
     }
 
     fn refs_mod(&self, x: isize) -> isize {
-        self.state().refs.fetch_add(1, Ordering::SeqCst) + x
+        self.state().refs.fetch_add(x, Ordering::SeqCst) + x
     }
 
     fn state(&self) -> &ArcState<T> {
@@ -75,3 +75,32 @@ struct ArcState<T> {
     allocator: AllocRef,
     refs: AtomicIsize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Arc;
+    use crate::alloc::{AllocRef, GlobalAllocator};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn drops_with_last_reference() {
+        let arc = Arc::new(Counted, AllocRef::new(GlobalAllocator));
+        let clone = Arc::clone(&arc);
+
+        drop(arc);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+
+        drop(clone);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+    }
+}
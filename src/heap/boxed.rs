@@ -36,6 +36,16 @@ impl<T> Box<T> {
         }
     }
 
+    /// # Safety
+    ///
+    /// The pointer and allocator must have been obtained from `Box::into_raw`.
+    pub unsafe fn from_raw(raw: *mut T, allocator: AllocRef) -> Self {
+        Self {
+            allocator: MaybeUninit::new(allocator),
+            raw,
+        }
+    }
+
     pub fn into_raw(self) -> (*mut T, AllocRef) {
         let allocator = unsafe { assume_init_read(&self.allocator) };
         let raw = self.raw;
@@ -50,12 +50,14 @@ mod atomic;
 mod backoff;
 mod barrier;
 mod cache_padded;
+#[cfg(feature = "global-collector")]
+pub mod compat;
 mod deferred;
 mod ebr;
 mod heap;
 mod lazy;
 mod mutex;
-mod queue;
+pub mod queue;
 mod shared;
 mod tag;
 pub mod tls2;
@@ -64,7 +66,16 @@ pub use atomic::Atomic;
 pub use backoff::Backoff;
 pub use cache_padded::CachePadded;
 pub use ebr::{
-    unprotected, Collector, CowShield, FullShield, Local, Shield, ThinShield, UnprotectedShield,
+    unprotected, AdvanceContext, AdvancePolicy, CapPolicy, Clock, Collector, CollectorBuilder,
+    CountPolicy, CowShield, FullShield, Local, OwnedFullShield, Participant, RetireHandle, Shield,
+    Stats, Synchronize, ThinShield, TimePolicy, UnprotectedShield, VolumePolicy, WeakCollector,
 };
+
+#[cfg(feature = "std")]
+pub use ebr::StdClock;
+
+#[cfg(feature = "global-collector")]
+pub use ebr::{default_collector, pin};
+
 pub use shared::Shared;
 pub use tag::{NullTag, Tag};
@@ -0,0 +1,501 @@
+// LICENSE NOTICE: Most of this code has been copied from the crossbeam repository with the MIT license.
+
+use crate::{
+    alloc::{handle_alloc_error, AllocRef, Layout},
+    Backoff, CachePadded,
+};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+/// A slot in a queue.
+struct Slot<T> {
+    /// The current stamp.
+    ///
+    /// If the stamp equals the tail, this node will be next written to. If it equals head + 1,
+    /// this node will be next read from.
+    stamp: AtomicUsize,
+
+    /// The value in this slot.
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A bounded multi-producer multi-consumer queue.
+///
+/// This queue allocates a fixed-capacity buffer on construction through the supplied `AllocRef`,
+/// which is used to store pushed elements. The queue cannot hold more elements than the buffer
+/// allows. Attempting to push an element into a full queue will fail and hand the element back,
+/// this makes it suitable for applying backpressure. Having a buffer allocated upfront makes
+/// this queue a bit faster than the unbounded [`Queue`].
+///
+/// # Progress guarantees
+///
+/// Like [`Queue`], `try_push` and `pop` never take a lock. A thread that has claimed a slot
+/// must however publish it before other threads can reuse or read that slot, threads that
+/// reach such a slot will spin and yield with a `Backoff` until it has been published.
+///
+/// # Examples
+///
+/// ```
+/// use flize::alloc::{AllocRef, GlobalAllocator};
+/// use flize::queue::ArrayQueue;
+///
+/// let queue = ArrayQueue::new(2, AllocRef::new(GlobalAllocator));
+/// assert_eq!(queue.try_push('a'), Ok(()));
+/// assert_eq!(queue.try_push('b'), Ok(()));
+/// assert_eq!(queue.try_push('c'), Err('c'));
+/// assert_eq!(queue.pop(), Some('a'));
+/// ```
+///
+/// [`Queue`]: struct.Queue.html
+pub struct ArrayQueue<T> {
+    /// The head of the queue.
+    ///
+    /// This value is a "stamp" consisting of an index into the buffer and a lap, but packed into a
+    /// single `usize`. The lower bits represent the index, while the upper bits represent the lap.
+    ///
+    /// Elements are popped from the head of the queue.
+    head: CachePadded<AtomicUsize>,
+
+    /// The tail of the queue.
+    ///
+    /// This value is a "stamp" consisting of an index into the buffer and a lap, but packed into a
+    /// single `usize`. The lower bits represent the index, while the upper bits represent the lap.
+    ///
+    /// Elements are pushed into the tail of the queue.
+    tail: CachePadded<AtomicUsize>,
+
+    /// The buffer holding slots.
+    buffer: *mut Slot<T>,
+
+    /// The queue capacity.
+    cap: usize,
+
+    /// A stamp with the value of `{ lap: 1, index: 0 }`.
+    one_lap: usize,
+
+    /// Indicates that dropping an `ArrayQueue<T>` may drop elements of type `T`.
+    _marker: PhantomData<T>,
+
+    allocator: AllocRef,
+}
+
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    /// Creates a new bounded queue with the given capacity,
+    /// the buffer is allocated with `allocator`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero or if the buffer would be larger than `isize::MAX` bytes.
+    /// Failing to allocate the buffer aborts the process, or panics without the `std` feature.
+    pub fn new(cap: usize, allocator: AllocRef) -> ArrayQueue<T> {
+        assert!(cap > 0, "capacity must be non-zero");
+
+        let layout = Self::buffer_layout(cap);
+        let buffer = allocator.alloc(&layout) as *mut Slot<T>;
+
+        if buffer.is_null() {
+            handle_alloc_error(&layout);
+        }
+
+        // Initialize stamps in the slots, the stamp of slot `i` is `{ lap: 0, index: i }`.
+        for i in 0..cap {
+            unsafe {
+                ptr::write(
+                    buffer.add(i),
+                    Slot {
+                        stamp: AtomicUsize::new(i),
+                        value: UnsafeCell::new(MaybeUninit::uninit()),
+                    },
+                );
+            }
+        }
+
+        // One lap is the smallest power of two greater than `cap`.
+        let one_lap = (cap + 1).next_power_of_two();
+
+        ArrayQueue {
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            buffer,
+            cap,
+            one_lap,
+            _marker: PhantomData,
+            allocator,
+        }
+    }
+
+    fn buffer_layout(cap: usize) -> Layout {
+        let align = mem::align_of::<Slot<T>>();
+
+        // allocators may round the size up to the alignment which must not exceed `isize::MAX` either
+        let size = mem::size_of::<Slot<T>>()
+            .checked_mul(cap)
+            .filter(|size| *size <= isize::MAX as usize - (align - 1))
+            .expect("capacity overflow");
+
+        unsafe { Layout::from_size_align_unchecked(size, align) }
+    }
+
+    /// Attempts to push an element into the queue.
+    ///
+    /// If the queue is full, the element is returned back as an error.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let backoff = Backoff::new();
+        let mut tail = self.tail.load(Ordering::Relaxed);
+
+        loop {
+            // Deconstruct the tail.
+            let index = tail & (self.one_lap - 1);
+            let lap = tail & !(self.one_lap - 1);
+
+            // Inspect the corresponding slot.
+            let slot = unsafe { &*self.buffer.add(index) };
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            // If the tail and the stamp match, we may attempt to push.
+            if tail == stamp {
+                let new_tail = if index + 1 < self.cap {
+                    // Same lap, incremented index.
+                    // Set to `{ lap: lap, index: index + 1 }`.
+                    tail + 1
+                } else {
+                    // One lap forward, index wraps around to zero.
+                    // Set to `{ lap: lap.wrapping_add(1), index: 0 }`.
+                    lap.wrapping_add(self.one_lap)
+                };
+
+                // Try moving the tail.
+                match self.tail.compare_exchange_weak(
+                    tail,
+                    new_tail,
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Write the value into the slot and update the stamp.
+                        unsafe {
+                            slot.value.get().write(MaybeUninit::new(value));
+                        }
+
+                        slot.stamp.store(tail + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(t) => {
+                        tail = t;
+                        backoff.spin();
+                    }
+                }
+            } else if stamp.wrapping_add(self.one_lap) == tail + 1 {
+                atomic::fence(Ordering::SeqCst);
+                let head = self.head.load(Ordering::Relaxed);
+
+                // If the head lags one lap behind the tail as well...
+                if head.wrapping_add(self.one_lap) == tail {
+                    // ...then the queue is full.
+                    return Err(value);
+                }
+
+                backoff.spin();
+                tail = self.tail.load(Ordering::Relaxed);
+            } else {
+                // Snooze because we need to wait for the stamp to update.
+                backoff.snooze();
+                tail = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Attempts to pop an element from the queue.
+    ///
+    /// If the queue is empty, `None` is returned.
+    pub fn pop(&self) -> Option<T> {
+        let backoff = Backoff::new();
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            // Deconstruct the head.
+            let index = head & (self.one_lap - 1);
+            let lap = head & !(self.one_lap - 1);
+
+            // Inspect the corresponding slot.
+            let slot = unsafe { &*self.buffer.add(index) };
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            // If the the stamp is ahead of the head by 1, we may attempt to pop.
+            if head + 1 == stamp {
+                let new = if index + 1 < self.cap {
+                    // Same lap, incremented index.
+                    // Set to `{ lap: lap, index: index + 1 }`.
+                    head + 1
+                } else {
+                    // One lap forward, index wraps around to zero.
+                    // Set to `{ lap: lap.wrapping_add(1), index: 0 }`.
+                    lap.wrapping_add(self.one_lap)
+                };
+
+                // Try moving the head.
+                match self.head.compare_exchange_weak(
+                    head,
+                    new,
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Read the value from the slot and update the stamp.
+                        let value = unsafe { slot.value.get().read().assume_init() };
+                        slot.stamp
+                            .store(head.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(h) => {
+                        head = h;
+                        backoff.spin();
+                    }
+                }
+            } else if stamp == head {
+                atomic::fence(Ordering::SeqCst);
+                let tail = self.tail.load(Ordering::Relaxed);
+
+                // If the tail equals the head, that means the queue is empty.
+                if tail == head {
+                    return None;
+                }
+
+                backoff.spin();
+                head = self.head.load(Ordering::Relaxed);
+            } else {
+                // Snooze because we need to wait for the stamp to update.
+                backoff.snooze();
+                head = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.load(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::SeqCst);
+
+        // Is the tail lagging one lap behind head?
+        // Is the tail equal to the head?
+        //
+        // Note: If the head changes just before we load the tail, that means there was a moment
+        // when the queue was not empty, so it is safe to just return `false`.
+        tail == head
+    }
+
+    /// Returns `true` if the queue is full.
+    pub fn is_full(&self) -> bool {
+        let tail = self.tail.load(Ordering::SeqCst);
+        let head = self.head.load(Ordering::SeqCst);
+
+        // Is the head lagging one lap behind tail?
+        //
+        // Note: If the tail changes just before we load the head, that means there was a moment
+        // when the queue was not full, so it is safe to just return `false`.
+        head.wrapping_add(self.one_lap) == tail
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        loop {
+            // Load the tail, then load the head.
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+
+            // If the tail didn't change, we've got consistent values to work with.
+            if self.tail.load(Ordering::SeqCst) == tail {
+                return self.len_between(head, tail);
+            }
+        }
+    }
+
+    fn len_between(&self, head: usize, tail: usize) -> usize {
+        let hix = head & (self.one_lap - 1);
+        let tix = tail & (self.one_lap - 1);
+
+        if hix < tix {
+            tix - hix
+        } else if hix > tix {
+            self.cap - hix + tix
+        } else if tail == head {
+            0
+        } else {
+            self.cap
+        }
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let hix = head & (self.one_lap - 1);
+        let len = self.len_between(head, tail);
+
+        unsafe {
+            // Loop over all slots that hold a value and drop them.
+            for i in 0..len {
+                // Compute the index of the next slot holding a value.
+                let index = if hix + i < self.cap {
+                    hix + i
+                } else {
+                    hix + i - self.cap
+                };
+
+                let slot = &*self.buffer.add(index);
+                let p = &mut *slot.value.get();
+                p.as_mut_ptr().drop_in_place();
+            }
+
+            let layout = Self::buffer_layout(self.cap);
+            self.allocator.dealloc(&layout, self.buffer as *mut u8);
+        }
+    }
+}
+
+impl<T> fmt::Debug for ArrayQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("ArrayQueue { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArrayQueue;
+    use crate::alloc::{AllocRef, GlobalAllocator};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    fn queue<T>(cap: usize) -> ArrayQueue<T> {
+        ArrayQueue::new(cap, AllocRef::new(GlobalAllocator))
+    }
+
+    #[test]
+    fn full_and_wrap() {
+        let queue = queue(3);
+        assert!(queue.is_empty());
+
+        for lap in 0..4 {
+            for i in 0..3 {
+                assert_eq!(queue.try_push(lap * 3 + i), Ok(()));
+            }
+
+            assert!(queue.is_full());
+            assert_eq!(queue.len(), 3);
+            assert_eq!(queue.try_push(100), Err(100));
+
+            for i in 0..3 {
+                assert_eq!(queue.pop(), Some(lap * 3 + i));
+            }
+
+            assert!(queue.is_empty());
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn drop_remaining() {
+        struct Counted<'a>(&'a AtomicUsize);
+
+        impl<'a> Drop for Counted<'a> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let queue = queue(8);
+
+        for _ in 0..6 {
+            assert!(queue.try_push(Counted(&drops)).is_ok());
+        }
+
+        drop(queue.pop());
+        drop(queue.pop());
+
+        for _ in 0..3 {
+            assert!(queue.try_push(Counted(&drops)).is_ok());
+        }
+
+        drop(queue);
+        assert_eq!(drops.load(Ordering::Relaxed), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn capacity_overflow() {
+        // the buffer fits in a `usize` but exceeds `isize::MAX` bytes
+        queue::<u64>(usize::MAX / 16);
+    }
+
+    #[test]
+    fn mpmc() {
+        const THREADS: usize = 4;
+        const ITEMS: usize = 10000;
+
+        let queue = Arc::new(queue(16));
+        let barrier = Arc::new(Barrier::new(THREADS * 2));
+        let sum = Arc::new(AtomicUsize::new(0));
+        let mut handles = Vec::new();
+
+        for _ in 0..THREADS {
+            let queue = Arc::clone(&queue);
+            let barrier = Arc::clone(&barrier);
+
+            handles.push(thread::spawn(move || {
+                barrier.wait();
+
+                for mut i in 1..=ITEMS {
+                    while let Err(back) = queue.try_push(i) {
+                        i = back;
+                        thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        for _ in 0..THREADS {
+            let queue = Arc::clone(&queue);
+            let barrier = Arc::clone(&barrier);
+            let sum = Arc::clone(&sum);
+
+            handles.push(thread::spawn(move || {
+                barrier.wait();
+                let mut popped = 0;
+
+                while popped < ITEMS {
+                    if let Some(i) = queue.pop() {
+                        sum.fetch_add(i, Ordering::Relaxed);
+                        popped += 1;
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(queue.is_empty());
+        assert_eq!(
+            sum.load(Ordering::Relaxed),
+            THREADS * ITEMS * (ITEMS + 1) / 2
+        );
+    }
+}
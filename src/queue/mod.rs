@@ -1,7 +1,13 @@
 // LICENSE NOTICE: Most of this code has been copied from the crossbeam repository with the MIT license.
 
+//! Concurrent multi-producer multi-consumer queues that allocate through an `AllocRef`.
+
+mod array;
+
+pub use array::ArrayQueue;
+
 use crate::{
-    alloc::{AllocRef, Layout},
+    alloc::{handle_alloc_error, AllocRef, Layout},
     Backoff, CachePadded,
 };
 use core::cell::UnsafeCell;
@@ -11,6 +17,9 @@ use core::mem::MaybeUninit;
 use core::ptr;
 use core::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
 
+#[cfg(feature = "std")]
+use crate::alloc::GlobalAllocator;
+
 // Bits indicating the state of a slot:
 // * If a value has been written into the slot, `WRITE` is set.
 // * If a value has been read from the slot, `READ` is set.
@@ -117,8 +126,33 @@ struct Position<T> {
 ///
 /// This queue is implemented as a linked list of segments, where each segment is a small buffer
 /// that can hold a handful of elements. There is no limit to how many elements can be in the queue
-/// at a time. However, since segments need to be dynamically allocated as elements get pushed,
-/// this queue is somewhat slower than [`ArrayQueue`].
+/// at a time. Segments are allocated and freed through the `AllocRef` supplied on construction
+/// which makes the queue usable in `no_std` environments with a custom allocator.
+/// If you need backpressure, see the bounded [`ArrayQueue`].
+///
+/// # Progress guarantees
+///
+/// `push` and `pop` never take a lock but they are not strictly lock-free either.
+/// A thread that has claimed a slot or is installing the next segment must finish
+/// before other threads can make progress past that point, they will spin and then
+/// yield with a `Backoff` while waiting. In practice this window is a handful of
+/// instructions long, a thread that is preempted inside it will however stall others
+/// that reach the same slot or segment boundary. `len` and `is_empty` are wait-free
+/// apart from retrying when they observe a concurrent `push`.
+///
+/// # Examples
+///
+/// ```
+/// use flize::queue::Queue;
+///
+/// let queue = Queue::default();
+/// queue.push(1);
+/// queue.push(2);
+/// assert_eq!(queue.len(), 2);
+/// assert_eq!(queue.pop(), Some(1));
+/// assert_eq!(queue.drain().collect::<Vec<_>>(), vec![2]);
+/// assert!(queue.is_empty());
+/// ```
 pub struct Queue<T> {
     /// The head of the queue.
     head: CachePadded<Position<T>>,
@@ -136,7 +170,7 @@ unsafe impl<T: Send> Send for Queue<T> {}
 unsafe impl<T: Send> Sync for Queue<T> {}
 
 impl<T> Queue<T> {
-    /// Creates a new unbounded queue.
+    /// Creates a new unbounded queue that allocates its segments with `allocator`.
     pub const fn new(allocator: AllocRef) -> Queue<T> {
         Queue {
             head: CachePadded::new(Position {
@@ -153,6 +187,8 @@ impl<T> Queue<T> {
     }
 
     /// Pushes an element into the queue.
+    ///
+    /// Failing to allocate a new segment aborts the process, or panics without the `std` feature.
     pub fn push(&self, value: T) {
         let backoff = Backoff::new();
         let mut tail = self.tail.index.load(Ordering::Acquire);
@@ -178,6 +214,11 @@ impl<T> Queue<T> {
 
                 next_block = Some(unsafe {
                     let ptr = self.allocator.alloc(&layout) as *mut Block<T>;
+
+                    if ptr.is_null() {
+                        handle_alloc_error(&layout);
+                    }
+
                     ptr::write(ptr, Block::new());
                     ptr
                 });
@@ -188,6 +229,11 @@ impl<T> Queue<T> {
                 let new = unsafe {
                     let layout = Layout::new::<Block<T>>();
                     let ptr = self.allocator.alloc(&layout) as *mut Block<T>;
+
+                    if ptr.is_null() {
+                        handle_alloc_error(&layout);
+                    }
+
                     ptr::write(ptr, Block::new());
                     ptr
                 };
@@ -334,6 +380,56 @@ impl<T> Queue<T> {
             }
         }
     }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.index.load(Ordering::SeqCst);
+        let tail = self.tail.index.load(Ordering::SeqCst);
+        head >> SHIFT == tail >> SHIFT
+    }
+
+    /// Returns the number of elements in the queue.
+    pub fn len(&self) -> usize {
+        loop {
+            // Load the tail index, then load the head index.
+            let mut tail = self.tail.index.load(Ordering::SeqCst);
+            let mut head = self.head.index.load(Ordering::SeqCst);
+
+            // If the tail index didn't change, we've got consistent indices to work with.
+            if self.tail.index.load(Ordering::SeqCst) == tail {
+                // Erase the lower bits.
+                tail &= !((1 << SHIFT) - 1);
+                head &= !((1 << SHIFT) - 1);
+
+                // Fix up indices if they fall onto block ends.
+                if (tail >> SHIFT) & (LAP - 1) == LAP - 1 {
+                    tail = tail.wrapping_add(1 << SHIFT);
+                }
+                if (head >> SHIFT) & (LAP - 1) == LAP - 1 {
+                    head = head.wrapping_add(1 << SHIFT);
+                }
+
+                // Rotate indices so that head falls into the first block.
+                let lap = (head >> SHIFT) / LAP;
+                tail = tail.wrapping_sub((lap * LAP) << SHIFT);
+                head = head.wrapping_sub((lap * LAP) << SHIFT);
+
+                // Remove the lower bits.
+                tail >>= SHIFT;
+                head >>= SHIFT;
+
+                // Return the difference minus the number of blocks between tail and head.
+                return tail - head - tail / LAP;
+            }
+        }
+    }
+
+    /// Returns an iterator that pops elements until the queue is observed empty.
+    ///
+    /// Elements pushed concurrently while draining may or may not be yielded.
+    pub fn drain(&self) -> Drain<'_, T> {
+        Drain { queue: self }
+    }
 }
 
 impl<T> Drop for Queue<T> {
@@ -381,3 +477,169 @@ impl<T> fmt::Debug for Queue<T> {
         f.pad("Queue { .. }")
     }
 }
+
+#[cfg(feature = "std")]
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new(AllocRef::new(GlobalAllocator))
+    }
+}
+
+impl<T> IntoIterator for Queue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { queue: self }
+    }
+}
+
+/// A draining iterator over the elements of a `Queue`.
+///
+/// This is created by [`Queue::drain`].
+pub struct Drain<'a, T> {
+    queue: &'a Queue<T>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+}
+
+impl<'a, T> fmt::Debug for Drain<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Drain { .. }")
+    }
+}
+
+/// An owning iterator over the elements of a `Queue`.
+///
+/// This is created by the `IntoIterator` implementation on `Queue`.
+pub struct IntoIter<T> {
+    queue: Queue<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+}
+
+impl<T> fmt::Debug for IntoIter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("IntoIter { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Queue, BLOCK_CAP};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn push_pop_len() {
+        let queue = Queue::default();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+
+        for i in 0..BLOCK_CAP * 3 {
+            queue.push(i);
+            assert_eq!(queue.len(), i + 1);
+        }
+
+        for i in 0..BLOCK_CAP * 3 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+
+        assert!(queue.is_empty());
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn drain_and_into_iter() {
+        let queue = Queue::default();
+        (0..100).for_each(|i| queue.push(i));
+        assert_eq!(queue.drain().take(50).count(), 50);
+        assert_eq!(queue.len(), 50);
+        assert_eq!(
+            queue.into_iter().collect::<Vec<_>>(),
+            (50..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn drop_remaining() {
+        struct Counted<'a>(&'a AtomicUsize);
+
+        impl<'a> Drop for Counted<'a> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let queue = Queue::default();
+        (0..BLOCK_CAP * 2 + 5).for_each(|_| queue.push(Counted(&drops)));
+        drop(queue.pop());
+        drop(queue);
+        assert_eq!(drops.load(Ordering::Relaxed), BLOCK_CAP * 2 + 5);
+    }
+
+    #[test]
+    fn mpmc() {
+        const THREADS: usize = 4;
+        const ITEMS: usize = 10000;
+
+        let queue = Arc::new(Queue::default());
+        let barrier = Arc::new(Barrier::new(THREADS * 2));
+        let sum = Arc::new(AtomicUsize::new(0));
+        let mut handles = Vec::new();
+
+        for _ in 0..THREADS {
+            let queue = Arc::clone(&queue);
+            let barrier = Arc::clone(&barrier);
+
+            handles.push(thread::spawn(move || {
+                barrier.wait();
+                (1..=ITEMS).for_each(|i| queue.push(i));
+            }));
+        }
+
+        for _ in 0..THREADS {
+            let queue = Arc::clone(&queue);
+            let barrier = Arc::clone(&barrier);
+            let sum = Arc::clone(&sum);
+
+            handles.push(thread::spawn(move || {
+                barrier.wait();
+                let mut popped = 0;
+
+                while popped < ITEMS {
+                    if let Some(i) = queue.pop() {
+                        sum.fetch_add(i, Ordering::Relaxed);
+                        popped += 1;
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(queue.is_empty());
+        assert_eq!(
+            sum.load(Ordering::Relaxed),
+            THREADS * ITEMS * (ITEMS + 1) / 2
+        );
+    }
+}
@@ -1,22 +1,90 @@
 mod priority_queue;
 mod thread_id;
 
+pub(crate) use thread_id::generation;
 pub use thread_id::{ThreadId, TlsProvider};
 
 #[cfg(feature = "std")]
 pub use thread_id::std_tls_provider;
 
-use crate::{alloc::AllocRef, heap::Box};
+use crate::{
+    alloc::{handle_alloc_error, AllocRef, Layout},
+    heap::Box,
+};
 use core::{
     marker::PhantomData,
-    mem,
-    sync::atomic::{self, AtomicUsize, Ordering},
+    mem, ptr,
+    sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering},
 };
 
-const MAX_THREADS: usize = 1024;
+/// The amount of entries in a chunk, consecutive ids share a chunk.
+const CHUNK_LEN: usize = 32;
+
+/// One bucket for every bit in a chunk index, bucket `n` holds pointers to `2^n` chunks.
+/// This covers every possible id without ever having to move a chunk.
+const BUCKETS: usize = mem::size_of::<usize>() * 8;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BUCKET: AtomicPtr<AtomicPtr<AtomicUsize>> = AtomicPtr::new(ptr::null_mut());
+
+/// The location of the entry of a thread id.
+#[derive(Debug, PartialEq, Eq)]
+struct Location {
+    bucket: usize,
+    chunk: usize,
+    entry: usize,
+}
+
+/// Maps a thread id to the bucket holding its chunk, the index of the chunk in that bucket
+/// and the index of its entry in the chunk.
+fn locate(id: usize) -> Location {
+    let key = id / CHUNK_LEN + 1;
+    let bucket = BUCKETS - 1 - key.leading_zeros() as usize;
+
+    Location {
+        bucket,
+        chunk: key - (1 << bucket),
+        entry: id % CHUNK_LEN,
+    }
+}
+
+fn bucket_len(bucket: usize) -> usize {
+    1 << bucket
+}
+
+/// The layout of an array of `len` elements of type `E`.
+fn array_layout<E>(len: usize) -> Layout {
+    let size = mem::size_of::<E>() * len;
+    unsafe { Layout::from_size_align_unchecked(size, mem::align_of::<E>()) }
+}
+
+fn bucket_layout(bucket: usize) -> Layout {
+    array_layout::<AtomicPtr<AtomicUsize>>(bucket_len(bucket))
+}
 
+fn chunk_layout() -> Layout {
+    array_layout::<AtomicUsize>(CHUNK_LEN)
+}
+
+/// A value in the table, linked into the list of all values.
+struct Entry<T> {
+    value: T,
+    next: *const Entry<T>,
+}
+
+/// A table of thread specific values indexed by thread id.
+///
+/// Entries are stored in fixed size chunks that are allocated lazily on first use so memory use
+/// scales with the amount of threads using the table. Chunks are found through buckets of chunk
+/// pointers of doubling size, also allocated lazily, so any thread id is supported at the cost
+/// of a pointer for every chunk of ids up to the highest id seen.
+///
+/// Every value is additionally linked into an intrusive list on creation. Values are never removed
+/// before the table is dropped so the list is push only and iterating it is safe at any time.
+/// This lets iteration cost scale with the amount of values instead of the highest id seen.
 pub(crate) struct ThreadLocal<T> {
-    entries: Box<[AtomicUsize; MAX_THREADS]>,
+    buckets: [AtomicPtr<AtomicPtr<AtomicUsize>>; BUCKETS],
+    head: AtomicPtr<Entry<T>>,
     snapshot: AtomicUsize,
     tls_provider: &'static dyn TlsProvider,
     _m0: PhantomData<*mut T>,
@@ -25,10 +93,9 @@ pub(crate) struct ThreadLocal<T> {
 
 impl<T> ThreadLocal<T> {
     pub fn new(tls_provider: &'static dyn TlsProvider, allocator: AllocRef) -> Self {
-        let arr = unsafe { mem::transmute([0_usize; MAX_THREADS]) };
-
         Self {
-            entries: Box::new(arr, allocator.clone()),
+            buckets: [EMPTY_BUCKET; BUCKETS],
+            head: AtomicPtr::new(ptr::null_mut()),
             snapshot: AtomicUsize::new(0),
             tls_provider,
             _m0: PhantomData,
@@ -36,33 +103,114 @@ impl<T> ThreadLocal<T> {
         }
     }
 
+    /// Loads the array of `len` elements stored in `slot`, allocating and installing it
+    /// if it doesn't exist yet. Elements of a new array are zeroed which must be a valid value of `E`.
+    fn array<E>(&self, slot: &AtomicPtr<E>, len: usize) -> *mut E {
+        let current = slot.load(Ordering::Acquire);
+
+        if !current.is_null() {
+            return current;
+        }
+
+        let layout = array_layout::<E>(len);
+        let new = self.allocator.alloc(&layout) as *mut E;
+
+        if new.is_null() {
+            handle_alloc_error(&layout);
+        }
+
+        unsafe {
+            ptr::write_bytes(new, 0, len);
+        }
+
+        match slot.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new,
+            Err(installed) => {
+                // another thread beat us to it, use theirs instead
+                self.allocator.dealloc(&layout, new as *mut u8);
+                installed
+            }
+        }
+    }
+
+    /// Loads the slot of the entry at the given location, allocating the bucket and chunk on the way.
+    fn slot(&self, location: &Location) -> &AtomicUsize {
+        // a null chunk pointer is a missing chunk and a zeroed `AtomicUsize` is a valid empty entry
+        let bucket = self.array(&self.buckets[location.bucket], bucket_len(location.bucket));
+        let chunk = self.array(unsafe { &*bucket.add(location.chunk) }, CHUNK_LEN);
+        unsafe { &*chunk.add(location.entry) }
+    }
+
     pub fn get<F>(&self, create: F) -> &T
     where
         F: FnOnce() -> T,
     {
         let id = self.tls_provider.get();
-        let entry = unsafe { self.entries.get_unchecked(id).load(Ordering::Relaxed) };
+        let slot = self.slot(&locate(id));
+        let entry = slot.load(Ordering::Relaxed);
 
         if entry == 0 {
             self.snapshot.fetch_add(1, Ordering::Release);
             atomic::compiler_fence(Ordering::SeqCst);
-            let item = Box::new(create(), self.allocator.clone());
-            let raw = Box::into_raw(item).0 as usize;
-            unsafe {
-                self.entries.get_unchecked(id).store(raw, Ordering::Release);
-            }
+
+            let item = Entry {
+                value: create(),
+                next: ptr::null(),
+            };
+
+            let raw = Box::into_raw(Box::new(item, self.allocator.clone())).0;
+            slot.store(raw as usize, Ordering::Release);
+            self.link(raw);
             atomic::compiler_fence(Ordering::SeqCst);
             self.snapshot.fetch_add(1, Ordering::Release);
-            unsafe { &*(raw as *const T) }
+            unsafe { &(*raw).value }
         } else {
-            unsafe { &*(entry as *const T) }
+            unsafe { &(*(entry as *const Entry<T>)).value }
+        }
+    }
+
+    /// Adds a value that isn't associated with any thread. It is visited when iterating
+    /// like any other value and lives until the table is dropped.
+    pub fn insert_detached(&self, value: T) -> &T {
+        self.snapshot.fetch_add(1, Ordering::Release);
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let item = Entry {
+            value,
+            next: ptr::null(),
+        };
+
+        let raw = Box::into_raw(Box::new(item, self.allocator.clone())).0;
+        self.link(raw);
+        atomic::compiler_fence(Ordering::SeqCst);
+        self.snapshot.fetch_add(1, Ordering::Release);
+        unsafe { &(*raw).value }
+    }
+
+    /// Pushes a freshly created entry onto the list of all entries.
+    fn link(&self, entry: *mut Entry<T>) {
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            unsafe {
+                (*entry).next = head;
+            }
+
+            match self
+                .head
+                .compare_exchange_weak(head, entry, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(new_head) => head = new_head,
+            }
         }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
-        self.entries
-            .iter()
-            .filter_map(|atomic| unsafe { (atomic.load(Ordering::Acquire) as *const T).as_ref() })
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.load(Ordering::Acquire),
+            _m0: PhantomData,
+        }
     }
 
     pub fn snapshot(&self) -> Snapshot {
@@ -75,7 +223,192 @@ impl<T> ThreadLocal<T> {
     }
 }
 
+impl<T> Drop for ThreadLocal<T> {
+    fn drop(&mut self) {
+        let mut entry = self.head.load(Ordering::Relaxed);
+
+        while !entry.is_null() {
+            unsafe {
+                let next = (*entry).next as *mut Entry<T>;
+                drop(Box::from_raw(entry, self.allocator.clone()));
+                entry = next;
+            }
+        }
+
+        for (bucket, atomic) in self.buckets.iter().enumerate() {
+            let ptr = atomic.load(Ordering::Relaxed);
+
+            if ptr.is_null() {
+                continue;
+            }
+
+            for index in 0..bucket_len(bucket) {
+                let chunk = unsafe { (*ptr.add(index)).load(Ordering::Relaxed) };
+
+                if !chunk.is_null() {
+                    self.allocator.dealloc(&chunk_layout(), chunk as *mut u8);
+                }
+            }
+
+            self.allocator
+                .dealloc(&bucket_layout(bucket), ptr as *mut u8);
+        }
+    }
+}
+
 unsafe impl<T> Send for ThreadLocal<T> where T: Send {}
 unsafe impl<T> Sync for ThreadLocal<T> where T: Sync {}
 
 pub(crate) struct Snapshot(usize);
+
+/// An iterator over all values in a `ThreadLocal`.
+pub(crate) struct Iter<'a, T> {
+    next: *const Entry<T>,
+    _m0: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        unsafe {
+            let entry = self.next.as_ref()?;
+            self.next = entry.next;
+            Some(&entry.value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bucket_layout, chunk_layout, locate, Entry, Location, ThreadLocal, TlsProvider, BUCKETS,
+        CHUNK_LEN,
+    };
+    use crate::alloc::{AllocRef, GlobalAllocator, Layout, VirtualAllocRef};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    thread_local! {
+        static ID: Cell<usize> = Cell::new(0);
+    }
+
+    #[derive(Debug)]
+    struct ManualTls;
+
+    impl TlsProvider for ManualTls {
+        fn get(&self) -> usize {
+            ID.with(|id| id.get())
+        }
+    }
+
+    /// Forwards to the global allocator while keeping track of the amount of live bytes.
+    struct Counting(&'static AtomicUsize);
+
+    unsafe impl VirtualAllocRef for Counting {
+        unsafe fn alloc(&self, layout: &Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            GlobalAllocator.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, layout: &Layout, ptr: *mut u8) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+            GlobalAllocator.dealloc(layout, ptr)
+        }
+
+        fn clone_untyped(&self) -> AllocRef {
+            AllocRef::new(Self(self.0))
+        }
+    }
+
+    #[test]
+    fn locate_ids() {
+        let location = |bucket, chunk, entry| Location {
+            bucket,
+            chunk,
+            entry,
+        };
+
+        assert_eq!(locate(0), location(0, 0, 0));
+        assert_eq!(locate(31), location(0, 0, 31));
+        assert_eq!(locate(32), location(1, 0, 0));
+        assert_eq!(locate(64), location(1, 1, 0));
+        assert_eq!(locate(96), location(2, 0, 0));
+        assert_eq!(locate(9000), location(8, 26, 8));
+
+        let last_chunk = usize::MAX / CHUNK_LEN;
+        let last_bucket = BUCKETS - 1 - (last_chunk + 1).leading_zeros() as usize;
+        assert_eq!(
+            locate(usize::MAX),
+            location(
+                last_bucket,
+                last_chunk + 1 - (1 << last_bucket),
+                CHUNK_LEN - 1
+            )
+        );
+    }
+
+    #[test]
+    fn ids_beyond_1024() {
+        const IDS: usize = 5000;
+
+        let table = ThreadLocal::new(&ManualTls, AllocRef::new(GlobalAllocator));
+
+        for id in 0..IDS {
+            ID.with(|cell| cell.set(id));
+            assert_eq!(*table.get(|| id), id);
+        }
+
+        for id in (0..IDS).rev() {
+            ID.with(|cell| cell.set(id));
+            assert_eq!(*table.get(|| unreachable!()), id);
+        }
+
+        assert_eq!(table.iter().count(), IDS);
+        assert_eq!(table.iter().sum::<usize>(), IDS * (IDS - 1) / 2);
+    }
+
+    #[test]
+    fn lazy_allocation() {
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+        let table = ThreadLocal::new(&ManualTls, AllocRef::new(Counting(&LIVE)));
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+
+        // ids 3 through 6 share the first chunk
+        for id in 3..7 {
+            ID.with(|cell| cell.set(id));
+            table.get(|| [0_u8; 32]);
+        }
+
+        let entries = 4 * Layout::new::<Entry<[u8; 32]>>().size();
+        assert_eq!(
+            LIVE.load(Ordering::Relaxed),
+            bucket_layout(0).size() + chunk_layout().size() + entries
+        );
+
+        drop(table);
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn high_id_allocation() {
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+        let table = ThreadLocal::new(&ManualTls, AllocRef::new(Counting(&LIVE)));
+        ID.with(|cell| cell.set(9000));
+        table.get(|| 0_usize);
+
+        // a single chunk is allocated along with the bucket of chunk pointers leading to it
+        let entry = Layout::new::<Entry<usize>>().size();
+        let live = LIVE.load(Ordering::Relaxed);
+        assert_eq!(
+            live,
+            bucket_layout(8).size() + chunk_layout().size() + entry
+        );
+        assert!(live < 4096);
+
+        drop(table);
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+}
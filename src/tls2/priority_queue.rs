@@ -1,22 +1,46 @@
-use super::MAX_THREADS;
+#[cfg(not(feature = "std"))]
 use tinyvec::ArrayVec;
 
+/// The maximum amount of items the queue can hold at once without the `std` feature.
+#[cfg(not(feature = "std"))]
+const CAPACITY: usize = 1024;
+
+/// With the standard library the queue grows as needed, otherwise it has a fixed capacity
+/// since there is no allocator for global state.
+#[cfg(feature = "std")]
+type Items<T> = std::vec::Vec<T>;
+
+#[cfg(not(feature = "std"))]
+type Items<T> = ArrayVec<[T; CAPACITY]>;
+
 /// A very simple priority queue.
 /// This is only ever accessed on thread setup and exit
 /// and thus performance is mostly irrelevant.
 pub struct PriorityQueue<T: Default> {
-    items: ArrayVec<[T; MAX_THREADS]>,
+    items: Items<T>,
 }
 
 impl<T: Ord + Default> PriorityQueue<T> {
     pub fn new() -> Self {
         Self {
-            items: ArrayVec::new(),
+            items: Items::new(),
         }
     }
 
-    pub fn push(&mut self, item: T) {
+    /// Push an item onto the queue, handing it back if the queue is full.
+    #[cfg(feature = "std")]
+    pub fn push(&mut self, item: T) -> Result<(), T> {
         self.items.push(item);
+        Ok(())
+    }
+
+    /// Push an item onto the queue, handing it back if the queue is full.
+    #[cfg(not(feature = "std"))]
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        match self.items.try_push(item) {
+            None => Ok(()),
+            Some(item) => Err(item),
+        }
     }
 
     /// Find the index of the least item and remove it from the list.
@@ -34,11 +58,19 @@ mod tests {
     #[test]
     fn pop_order() {
         let mut queue = PriorityQueue::new();
-        queue.push(7);
-        queue.push(9);
-        queue.push(4);
+        queue.push(7).unwrap();
+        queue.push(9).unwrap();
+        queue.push(4).unwrap();
         assert_eq!(queue.pop(), Some(4));
         assert_eq!(queue.pop(), Some(7));
         assert_eq!(queue.pop(), Some(9));
     }
+
+    #[test]
+    fn grows() {
+        let mut queue = PriorityQueue::new();
+        (0..4096).rev().for_each(|i| queue.push(i).unwrap());
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.pop(), Some(1));
+    }
 }
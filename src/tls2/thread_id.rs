@@ -7,6 +7,11 @@ use super::priority_queue::PriorityQueue;
 use crate::lazy::Lazy;
 use crate::mutex::Mutex;
 use core::fmt::Debug;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use core::{mem, ptr};
+
+#[cfg(feature = "std")]
+use crate::alloc::{handle_alloc_error, GlobalAllocator, Layout, VirtualAllocRef};
 
 #[cfg(feature = "std")]
 use core::cell::RefCell;
@@ -51,6 +56,100 @@ thread_local! {
     static TLS_VALUE: RefCell<Option<ThreadId>> = RefCell::new(None);
 }
 
+/// The amount of ids whose generations are stored in a static table.
+const STATIC_IDS: usize = 1024;
+
+/// One bucket for every bit in an id, bucket `n` holds the generations of `2^n` ids.
+const BUCKETS: usize = mem::size_of::<usize>() * 8;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const GENERATION_ZERO: AtomicUsize = AtomicUsize::new(0);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BUCKET: AtomicPtr<AtomicUsize> = AtomicPtr::new(ptr::null_mut());
+
+/// The amount of times each of the lowest ids has been released.
+static GENERATIONS: [AtomicUsize; STATIC_IDS] = [GENERATION_ZERO; STATIC_IDS];
+
+/// Generations of higher ids, in buckets of doubling size that are allocated the first time
+/// an id in them is released. Buckets are never freed so references into them are `'static`.
+static OVERFLOW: [AtomicPtr<AtomicUsize>; BUCKETS] = [EMPTY_BUCKET; BUCKETS];
+
+/// Maps an id not in the static table to its bucket and index in that bucket.
+fn locate_overflow(id: usize) -> (usize, usize) {
+    let key = id - STATIC_IDS + 1;
+    let bucket = BUCKETS - 1 - key.leading_zeros() as usize;
+    (bucket, key - (1 << bucket))
+}
+
+#[cfg(feature = "std")]
+fn overflow_layout(bucket: usize) -> Layout {
+    let size = mem::size_of::<AtomicUsize>() << bucket;
+    unsafe { Layout::from_size_align_unchecked(size, mem::align_of::<AtomicUsize>()) }
+}
+
+/// Loads the generation counter of an id if it has one.
+fn generation_slot(id: usize) -> Option<&'static AtomicUsize> {
+    if let Some(generation) = GENERATIONS.get(id) {
+        return Some(generation);
+    }
+
+    let (bucket, index) = locate_overflow(id);
+    let ptr = OVERFLOW[bucket].load(Ordering::Acquire);
+
+    if ptr.is_null() {
+        None
+    } else {
+        unsafe { Some(&*ptr.add(index)) }
+    }
+}
+
+/// Loads the generation counter of an id, allocating its bucket if needed.
+/// Returns `None` if no counter could be allocated.
+///
+/// This must be called with the id allocator locked.
+fn generation_slot_or_alloc(id: usize) -> Option<&'static AtomicUsize> {
+    if let Some(generation) = generation_slot(id) {
+        return Some(generation);
+    }
+
+    let (bucket, index) = locate_overflow(id);
+    let ptr = alloc_overflow_bucket(bucket)?;
+    OVERFLOW[bucket].store(ptr, Ordering::Release);
+    unsafe { Some(&*ptr.add(index)) }
+}
+
+#[cfg(feature = "std")]
+fn alloc_overflow_bucket(bucket: usize) -> Option<*mut AtomicUsize> {
+    let layout = overflow_layout(bucket);
+    let ptr = unsafe { GlobalAllocator.alloc(&layout) } as *mut AtomicUsize;
+
+    if ptr.is_null() {
+        handle_alloc_error(&layout);
+    }
+
+    unsafe {
+        ptr::write_bytes(ptr, 0, 1 << bucket);
+    }
+
+    Some(ptr)
+}
+
+/// Without the standard library there is no allocator for global state.
+#[cfg(not(feature = "std"))]
+fn alloc_overflow_bucket(_bucket: usize) -> Option<*mut AtomicUsize> {
+    None
+}
+
+/// Returns the generation of an id, which changes every time the id is released.
+/// Together with the id this tells apart threads that were handed the same id.
+///
+/// This only reads the generation without synchronization and must be called with an id
+/// held by the calling thread.
+pub(crate) fn generation(id: usize) -> usize {
+    generation_slot(id).map_or(0, |generation| generation.load(Ordering::Relaxed))
+}
+
 /// This structure allocates ids.
 /// It is compose of a `limit` integer and a list of free ids lesser than `limit`.
 /// If an allocation is attempted and the list is empty,
@@ -77,7 +176,13 @@ impl IdAllocator {
     }
 
     fn deallocate(&mut self, id: usize) {
-        self.free.push(id);
+        // Without the `std` feature the generation of a high id can't be tracked and the free list
+        // is bounded, if either is the case the id is never handed out again.
+        if let Some(generation) = generation_slot_or_alloc(id) {
+            // the lock orders this before the id is handed out again
+            generation.fetch_add(1, Ordering::Relaxed);
+            let _ = self.free.push(id);
+        }
     }
 }
 
@@ -103,3 +208,22 @@ impl Drop for ThreadId {
         ID_ALLOCATOR.get().lock().deallocate(self.0);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{generation, IdAllocator};
+
+    #[test]
+    fn high_ids_are_reused() {
+        let mut allocator = IdAllocator::new();
+
+        // skip far past the ids handed out by other tests
+        allocator.limit = 5000;
+        let id = allocator.allocate();
+        let before = generation(id);
+        allocator.deallocate(id);
+
+        assert_eq!(allocator.allocate(), id);
+        assert_ne!(generation(id), before);
+    }
+}